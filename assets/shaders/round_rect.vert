@@ -0,0 +1,40 @@
+#version 330 core
+
+// a corner of the unit quad, the same for every instance
+layout (location = 0) in vec2 Corner;
+
+// one of these per round rect, see RoundRectInstance in quick_draw.rs
+layout (location = 1) in vec2 Center;
+layout (location = 2) in vec2 HalfExtents;
+layout (location = 3) in float Angle;
+layout (location = 4) in float Radius;
+layout (location = 5) in float OutlineWidth;
+layout (location = 6) in vec4 FillColor;
+layout (location = 7) in vec4 OutlineColor;
+
+out vec2 local;
+out vec4 color;
+out vec4 outline_color;
+out vec2 half_extents;
+out float radius;
+out float outline_width;
+
+uniform mat4 camera;
+uniform mat4 projection;
+// how far past the edge the quad goes so it can fade out, a pixel in world units
+uniform float padding;
+
+void main()
+{
+    // same as RoundRect::corners in render.rs
+    local = Corner * (HalfExtents + vec2(Radius + padding));
+    vec2 axis = vec2(cos(Angle), sin(Angle));
+    vec2 position = Center + vec2(axis.x * local.x - axis.y * local.y, axis.y * local.x + axis.x * local.y);
+
+    color = FillColor;
+    outline_color = OutlineColor;
+    half_extents = HalfExtents;
+    radius = Radius;
+    outline_width = OutlineWidth;
+    gl_Position = projection * camera * vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,38 @@
+#version 330 core
+
+out vec4 Color;
+in vec2 local;
+in vec4 color;
+in vec4 outline_color;
+in vec2 half_extents;
+in float radius;
+in float outline_width;
+
+// same as RoundRect::distance and edge_coverage in render.rs
+float round_rect_distance(vec2 p) {
+    vec2 outside = abs(p) - half_extents;
+    return length(max(outside, 0.0)) + min(max(outside.x, outside.y), 0.0) - radius;
+}
+
+// smoothstep(0.5, -0.5, x) would be the same, but GLSL leaves it undefined with the edges
+// the wrong way around
+float coverage(float distance, float pixel) {
+    return 1.0 - smoothstep(-0.5, 0.5, distance / pixel);
+}
+
+void main()
+{
+    // a polygon, see POLYGON_RADIUS in quick_draw.rs
+    if(radius < 0.0) {
+        Color = color;
+        return;
+    }
+    float distance = round_rect_distance(local);
+    // how far apart neighbouring pixels are, so edges stay a pixel wide at any zoom
+    float pixel = max(fwidth(distance), 1e-6);
+    vec4 c = color;
+    if(outline_width > 0.0) {
+        c = mix(color, outline_color, 1.0 - coverage(distance + outline_width, pixel));
+    }
+    Color = vec4(c.rgb, c.a * coverage(distance, pixel));
+}
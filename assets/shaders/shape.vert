@@ -0,0 +1,30 @@
+#version 330 core
+
+layout (location = 0) in vec2 Position;
+layout (location = 1) in vec2 Local;
+layout (location = 2) in vec4 VertexColor;
+layout (location = 3) in vec4 OutlineColor;
+layout (location = 4) in vec2 HalfExtents;
+layout (location = 5) in float Radius;
+layout (location = 6) in float OutlineWidth;
+
+out vec2 local;
+out vec4 color;
+out vec4 outline_color;
+out vec2 half_extents;
+out float radius;
+out float outline_width;
+
+uniform mat4 camera;
+uniform mat4 projection;
+
+void main()
+{
+    local = Local;
+    color = VertexColor;
+    outline_color = OutlineColor;
+    half_extents = HalfExtents;
+    radius = Radius;
+    outline_width = OutlineWidth;
+    gl_Position = projection * camera * vec4(Position, 0.0, 1.0);
+}
@@ -0,0 +1,282 @@
+use crate::world::World;
+
+use serde::{Deserialize, Serialize};
+
+type V2 = na::Vector2<f32>;
+
+/// How the camera chases the player around
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    /// Pixels per world unit to start with
+    pub zoom: f32,
+    /// Whether to start out keeping the ground down, see `Camera2D::planet_up`
+    pub planet_up: bool,
+    /// The player can move this far from the center of the screen, in world units, before the
+    /// camera starts following
+    pub deadzone: f32,
+    /// How many seconds of the player's velocity the camera looks ahead by
+    pub look_ahead: f32,
+    /// How quickly the camera catches up, bigger is snappier. Roughly the fraction of the way it
+    /// goes each second.
+    pub follow_speed: f32,
+    /// How quickly the camera turns to keep the ground down
+    pub rotation_speed: f32,
+    /// Pixels per world unit
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// How much one notch of the mouse wheel multiplies the zoom by
+    pub zoom_step: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            zoom: 8.0,
+            planet_up: false,
+            deadzone: 3.0,
+            look_ahead: 0.3,
+            follow_speed: 4.0,
+            rotation_speed: 3.0,
+            min_zoom: 1.0,
+            max_zoom: 64.0,
+            zoom_step: 1.1,
+        }
+    }
+}
+
+/// What part of the world is on screen. Screen coordinates are in pixels from the top left of the
+/// viewport, with y going down.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera2D {
+    /// The point in the world at the center of the screen
+    pub position: V2,
+    /// Pixels per world unit
+    pub zoom: f32,
+    /// In radians, how far the world is turned on screen. That's clockwise, since y goes down.
+    pub rotation: f32,
+    /// Turns the camera so the nearest planet is always below the player
+    pub planet_up: bool,
+    pub config: CameraConfig,
+}
+
+impl Camera2D {
+    pub fn new(position: V2, zoom: f32) -> Self {
+        Camera2D {
+            position,
+            zoom,
+            rotation: 0.0,
+            planet_up: false,
+            config: CameraConfig::default(),
+        }
+    }
+
+    /// A camera starting at `position` with the zoom and `planet_up` from `config`
+    pub fn with_config(position: V2, config: CameraConfig) -> Self {
+        Camera2D {
+            position,
+            zoom: config.zoom,
+            rotation: 0.0,
+            planet_up: config.planet_up,
+            config,
+        }
+    }
+
+    /// Takes world coordinates to screen coordinates, to go with a projection of
+    /// `Orthographic3::new(0.0, width, height, 0.0, -1.0, 1.0)`
+    pub fn matrix(&self, viewport: V2) -> na::Matrix4<f32> {
+        na::Matrix4::new_translation(&(viewport / 2.0).push(0.0))
+            * na::Matrix4::new_scaling(self.zoom)
+            * na::Rotation3::from_axis_angle(&na::Vector3::z_axis(), self.rotation).to_homogeneous()
+            * na::Matrix4::new_translation(&(-self.position).push(0.0))
+    }
+
+    pub fn world_to_screen(&self, point: V2, viewport: V2) -> V2 {
+        viewport / 2.0 + na::Rotation2::new(self.rotation) * (point - self.position) * self.zoom
+    }
+
+    pub fn screen_to_world(&self, point: V2, viewport: V2) -> V2 {
+        self.position + na::Rotation2::new(-self.rotation) * (point - viewport / 2.0) / self.zoom
+    }
+
+    /// Zooms in by `notches` of the mouse wheel (out if negative), keeping whatever's under
+    /// `anchor` on the screen where it is
+    pub fn zoom_by(&mut self, notches: f32, anchor: V2, viewport: V2) {
+        let before = self.screen_to_world(anchor, viewport);
+        self.zoom = (self.zoom * self.config.zoom_step.powf(notches))
+            .clamp(self.config.min_zoom, self.config.max_zoom);
+        let after = self.screen_to_world(anchor, viewport);
+        self.position += before - after;
+    }
+
+    /// Moves towards `target` (and a bit ahead of where its `velocity` is taking it), once it's
+    /// further away than the deadzone. `dt` is the seconds since the last update.
+    pub fn follow(&mut self, target: V2, velocity: V2, dt: f32) {
+        let ahead = target + velocity * self.config.look_ahead;
+        let offset = ahead - self.position;
+        let distance = offset.norm();
+        if distance <= self.config.deadzone {
+            return;
+        }
+        let goal = ahead - offset / distance * self.config.deadzone;
+        self.position += (goal - self.position) * approach(self.config.follow_speed, dt);
+    }
+
+    /// Turns towards having `down` point at the bottom of the screen
+    pub fn turn_towards_down(&mut self, down: V2, dt: f32) {
+        if down.norm_squared() == 0.0 {
+            return;
+        }
+        let goal = std::f32::consts::FRAC_PI_2 - down.y.atan2(down.x);
+        self.rotation +=
+            shortest_angle(goal - self.rotation) * approach(self.config.rotation_speed, dt);
+        self.rotation = shortest_angle(self.rotation);
+    }
+
+    /// Follows the player, and keeps the ground down if `planet_up` is on
+    pub fn update(&mut self, world: &World, alpha: f64, dt: f32) {
+        let player = world.player();
+        let position = world.interpolated_position(world.player_handle(), alpha);
+        self.follow(
+            na::convert(position.translation.vector),
+            na::convert(*player.linvel()),
+            dt,
+        );
+        let down = if self.planet_up {
+            na::convert(world.player_down())
+        } else {
+            V2::y()
+        };
+        self.turn_towards_down(down, dt);
+    }
+}
+
+/// How much of the remaining distance to cover in `dt` seconds, so catching up looks the same
+/// whatever the frame rate
+fn approach(speed: f32, dt: f32) -> f32 {
+    1.0 - (-speed * dt).exp()
+}
+
+/// The same angle, between -pi and pi
+fn shortest_angle(angle: f32) -> f32 {
+    let tau = std::f32::consts::TAU;
+    angle - tau * (angle / tau).round()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::WorldConfig;
+
+    fn close(a: V2, b: V2) -> bool {
+        (a - b).norm() < 1e-3
+    }
+
+    #[test]
+    fn screen_and_world_round_trip() {
+        let viewport = V2::new(800.0, 600.0);
+        let mut camera = Camera2D::new(V2::new(10.0, -5.0), 4.0);
+        assert_eq!(
+            camera.world_to_screen(V2::new(10.0, -5.0), viewport),
+            V2::new(400.0, 300.0)
+        );
+        assert_eq!(
+            camera.world_to_screen(V2::new(11.0, -5.0), viewport),
+            V2::new(404.0, 300.0)
+        );
+        assert_eq!(
+            camera.screen_to_world(V2::new(0.0, 0.0), viewport),
+            V2::new(-90.0, -80.0)
+        );
+
+        camera.rotation = std::f32::consts::FRAC_PI_2;
+        // turned a quarter, right in the world is down on screen
+        assert!(close(
+            camera.world_to_screen(V2::new(11.0, -5.0), viewport),
+            V2::new(400.0, 304.0)
+        ));
+        for point in [V2::new(3.0, 7.0), V2::new(-100.0, 20.5)].iter() {
+            let screen = camera.world_to_screen(*point, viewport);
+            assert!(close(camera.screen_to_world(screen, viewport), *point));
+            // the matrix agrees with the conversions
+            let transformed = camera
+                .matrix(viewport)
+                .transform_point(&point.push(0.0).into());
+            assert!(close(transformed.coords.xy(), screen));
+        }
+    }
+
+    #[test]
+    fn zooms_around_the_mouse() {
+        let viewport = V2::new(800.0, 600.0);
+        let mut camera = Camera2D::new(V2::zeros(), 8.0);
+        let mouse = V2::new(100.0, 500.0);
+        let under_mouse = camera.screen_to_world(mouse, viewport);
+        camera.zoom_by(3.0, mouse, viewport);
+        assert!((camera.zoom - 8.0 * 1.1f32.powi(3)).abs() < 1e-4);
+        assert!(close(camera.screen_to_world(mouse, viewport), under_mouse));
+
+        camera.zoom_by(-1000.0, mouse, viewport);
+        assert_eq!(camera.zoom, camera.config.min_zoom);
+    }
+
+    #[test]
+    fn follows_outside_the_deadzone() {
+        let mut camera = Camera2D::new(V2::zeros(), 8.0);
+        camera.follow(V2::new(2.0, 0.0), V2::zeros(), 1.0 / 60.0);
+        assert_eq!(camera.position, V2::zeros());
+
+        // eventually ends up at the edge of the deadzone, looking ahead of the target
+        for _ in 0..600 {
+            camera.follow(V2::new(20.0, 0.0), V2::new(0.0, 10.0), 1.0 / 60.0);
+        }
+        let ahead = V2::new(20.0, 3.0);
+        assert!(((ahead - camera.position).norm() - camera.config.deadzone).abs() < 1e-3);
+
+        // no matter how often it's updated
+        let mut fast = Camera2D::new(V2::zeros(), 8.0);
+        let mut slow = fast.clone();
+        for _ in 0..4 {
+            fast.follow(V2::new(50.0, 0.0), V2::zeros(), 0.25);
+        }
+        slow.follow(V2::new(50.0, 0.0), V2::zeros(), 1.0);
+        assert!(fast.position.x > 10.0);
+        assert!((fast.position.x - slow.position.x).abs() < 1.0);
+    }
+
+    #[test]
+    fn turns_the_shortest_way() {
+        let mut camera = Camera2D::new(V2::zeros(), 8.0);
+        camera.rotation = 3.0;
+        // ground to the right, so the world turns a quarter to put it below
+        for _ in 0..600 {
+            camera.turn_towards_down(V2::new(1.0, 0.0), 1.0 / 60.0);
+        }
+        assert!((camera.rotation - std::f32::consts::FRAC_PI_2).abs() < 1e-3);
+
+        // just the other side of pi
+        camera.rotation = 3.1;
+        camera.turn_towards_down(V2::new(-0.04, -1.0), 1.0);
+        // went past pi instead of back around through zero
+        assert!(camera.rotation.abs() > 3.0);
+    }
+
+    #[test]
+    fn keeps_the_planet_below() {
+        let mut world = World::new(WorldConfig::default());
+        for _ in 0..300 {
+            world.step(&Default::default());
+        }
+        let mut camera = Camera2D::new(V2::zeros(), 8.0);
+        camera.planet_up = true;
+        for _ in 0..600 {
+            camera.update(&world, 1.0, 1.0 / 60.0);
+        }
+        let viewport = V2::new(800.0, 600.0);
+        let player: V2 = na::convert(world.player().position().translation.vector);
+        let planet = camera.world_to_screen(V2::new(0.0, 140.0), viewport);
+        let player = camera.world_to_screen(player, viewport);
+        assert!((planet.x - player.x).abs() < 1.0);
+        assert!(planet.y > player.y);
+    }
+}
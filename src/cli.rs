@@ -0,0 +1,175 @@
+use crate::config::Config;
+use crate::gl_backend::GlBackend;
+
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: planets [options]
+
+Options:
+  --config <path>        Read settings from this file instead of planets.toml
+  --level <path>         Play this level instead of the built in one
+  --benchmark <bodies>   Play a level with this many bodies, to see how well it runs
+  --width <n>            Window width
+  --height <n>           Window height
+  --fullscreen           Cover the whole screen
+  --windowed             Don't cover the whole screen
+  --vsync, --no-vsync    Wait, or don't, for the display before showing each frame
+  --letterbox            Keep the window's starting shape, with bars around the picture
+  --gl <backend>         Draw with gl33, gles30 or gles20 instead of the best that works
+  --headless <steps>     Run this many steps with no input and no window, then print a hash
+                         of the world
+  --record <path>        Save everything that's played as a recording
+  --replay <path>        Play a recording back with no window, then print a hash of the world
+  --frames <dir>         With --replay, save every frame as a png in this directory
+  --frame-count <n>      With --frames, only save this many frames
+  --screenshot <path>    Save the level as it starts as a png, with no window
+  --help                 Show this
+";
+
+/// What was asked for on the command line. Settings are left as `None` when not given, so they
+/// don't replace what's in the config file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Args {
+    pub config: Option<String>,
+    pub level: Option<String>,
+    /// How many bodies to put in the benchmark level, see `Level::benchmark`
+    pub benchmark: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fullscreen: Option<bool>,
+    pub vsync: Option<bool>,
+    pub letterbox: Option<bool>,
+    pub gl: Option<GlBackend>,
+    pub headless: Option<u64>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    pub frames: Option<String>,
+    pub frame_count: Option<usize>,
+    pub screenshot: Option<String>,
+    pub help: bool,
+}
+
+fn number<T: FromStr>(arg: &str, value: String) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} needs a number, not {}", arg, value))
+}
+
+impl Args {
+    /// Parses the arguments after the program's name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("{} needs a value after it", arg))
+            };
+            match arg.as_str() {
+                "--config" => parsed.config = Some(value()?),
+                "--level" => parsed.level = Some(value()?),
+                "--benchmark" => parsed.benchmark = Some(number(&arg, value()?)?),
+                "--width" => parsed.width = Some(number(&arg, value()?)?),
+                "--height" => parsed.height = Some(number(&arg, value()?)?),
+                "--fullscreen" => parsed.fullscreen = Some(true),
+                "--windowed" => parsed.fullscreen = Some(false),
+                "--vsync" => parsed.vsync = Some(true),
+                "--no-vsync" => parsed.vsync = Some(false),
+                "--letterbox" => parsed.letterbox = Some(true),
+                "--gl" => parsed.gl = Some(value()?.parse()?),
+                "--headless" => parsed.headless = Some(number(&arg, value()?)?),
+                "--record" => parsed.record = Some(value()?),
+                "--replay" => parsed.replay = Some(value()?),
+                "--frames" => parsed.frames = Some(value()?),
+                "--frame-count" => parsed.frame_count = Some(number(&arg, value()?)?),
+                "--screenshot" => parsed.screenshot = Some(value()?),
+                "--help" | "-h" => parsed.help = true,
+                _ => return Err(format!("Unknown argument {}, see --help", arg)),
+            }
+        }
+        if parsed.level.is_some() && parsed.benchmark.is_some() {
+            return Err("--level and --benchmark can't be used together".to_string());
+        }
+        if parsed.frames.is_some() && parsed.replay.is_none() {
+            return Err("--frames needs a --replay to render".to_string());
+        }
+        if parsed.frame_count.is_some() && parsed.frames.is_none() {
+            return Err("--frame-count needs --frames".to_string());
+        }
+        Ok(parsed)
+    }
+
+    /// Replaces whatever the config says with what was given on the command line
+    pub fn apply(&self, config: &mut Config) -> Result<(), String> {
+        let window = &mut config.window;
+        window.width = self.width.unwrap_or(window.width);
+        window.height = self.height.unwrap_or(window.height);
+        window.fullscreen = self.fullscreen.unwrap_or(window.fullscreen);
+        window.vsync = self.vsync.unwrap_or(window.vsync);
+        window.letterbox = self.letterbox.unwrap_or(window.letterbox);
+        window.backend = self.gl.or(window.backend);
+        config.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Args, String> {
+        Args::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_arguments() {
+        assert_eq!(parse("").unwrap(), Args::default());
+        let args = parse("--level a.json --width 640 --no-vsync --fullscreen").unwrap();
+        assert_eq!(args.level.as_deref(), Some("a.json"));
+        assert_eq!(args.width, Some(640));
+        assert_eq!(args.height, None);
+        assert_eq!(args.vsync, Some(false));
+        assert_eq!(args.fullscreen, Some(true));
+        assert_eq!(parse("--gl gles20").unwrap().gl, Some(GlBackend::Gles20));
+        let args = parse("--replay r.rec --frames out --frame-count 3").unwrap();
+        assert_eq!(args.frame_count, Some(3));
+        assert!(parse("--help").unwrap().help);
+    }
+
+    #[test]
+    fn explains_bad_arguments() {
+        assert_eq!(
+            parse("--level").unwrap_err(),
+            "--level needs a value after it"
+        );
+        assert_eq!(
+            parse("--width wide").unwrap_err(),
+            "--width needs a number, not wide"
+        );
+        assert_eq!(
+            parse("--headless -5").unwrap_err(),
+            "--headless needs a number, not -5"
+        );
+        assert!(parse("--fulscreen").unwrap_err().contains("--fulscreen"));
+        assert!(parse("--gl vulkan").unwrap_err().contains("vulkan"));
+        assert!(parse("--frames out").unwrap_err().contains("--replay"));
+        assert!(parse("--level a.json --benchmark 10")
+            .unwrap_err()
+            .contains("together"));
+    }
+
+    #[test]
+    fn overrides_the_config() {
+        let mut config = Config::parse("[window]\nwidth = 640\nheight = 480").unwrap();
+        parse("--height 200 --letterbox")
+            .unwrap()
+            .apply(&mut config)
+            .unwrap();
+        assert_eq!(config.window.width, 640);
+        assert_eq!(config.window.height, 200);
+        assert!(config.window.letterbox);
+        assert!(config.window.vsync);
+        let err = parse("--width 0").unwrap().apply(&mut config).unwrap_err();
+        assert_eq!(err, "window.width must be positive, got 0");
+    }
+}
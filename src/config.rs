@@ -0,0 +1,302 @@
+use crate::camera::CameraConfig;
+use crate::gl_backend::GlBackend;
+use crate::level::{not_negative, positive, Level};
+use crate::player_controller::ControllerConfig;
+use crate::world::WorldConfig;
+
+use rapier2d_f64::dynamics::IntegrationParameters;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Every tunable the game has, read from a toml file like `planets.toml`. Anything left out of
+/// the file keeps its default, and levels can change the `physics`, `player` and `camera` parts
+/// for themselves.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub physics: PhysicsConfig,
+    pub player: ControllerConfig,
+    pub camera: CameraConfig,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub title: String,
+    /// In window coordinates, which can be fewer than the pixels on high DPI displays
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// Keeps the shape of `width` and `height` when the window is resized, with bars around the
+    /// picture, instead of showing more of the world
+    pub letterbox: bool,
+    /// Which GL to draw with, `None` tries each of `GlBackend::ALL` until one works
+    pub backend: Option<GlBackend>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            title: "explain".to_string(),
+            width: 1000,
+            height: 900,
+            fullscreen: false,
+            vsync: true,
+            letterbox: false,
+            backend: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhysicsConfig {
+    pub steps_per_second: f64,
+    /// The most steps run before drawing a frame, if the game falls further behind than this it
+    /// slows down instead of freezing up
+    pub max_substeps: u32,
+    /// Multiplies the pull of every planet
+    pub gravity_scale: f64,
+    pub player_linear_damping: f64,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        let world = WorldConfig::default();
+        PhysicsConfig {
+            steps_per_second: 1.0 / world.integration_parameters.dt,
+            max_substeps: 8,
+            gravity_scale: world.gravity_scale,
+            player_linear_damping: world.player_linear_damping,
+        }
+    }
+}
+
+/// Replaces the fields of `base` that are in `overrides`, failing on any that `T` doesn't have
+fn with_overrides<T: Serialize + DeserializeOwned>(
+    base: &T,
+    overrides: &serde_json::Map<String, serde_json::Value>,
+) -> Result<T, String> {
+    let mut value = serde_json::to_value(base).map_err(|e| e.to_string())?;
+    if let serde_json::Value::Object(fields) = &mut value {
+        for (name, field) in overrides.iter() {
+            fields.insert(name.clone(), field.clone());
+        }
+    }
+    serde_json::from_value(value).map_err(|e| e.to_string())
+}
+
+impl Config {
+    /// Parses a config file, errors say which setting is wrong and why
+    pub fn parse(toml_source: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(toml_source).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Config, String> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        Self::parse(&source).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// This config with whatever `level` changes about it
+    pub fn for_level(&self, level: &Level) -> Result<Config, String> {
+        let overrides = &level.overrides;
+        let config = Config {
+            window: self.window.clone(),
+            physics: with_overrides(&self.physics, &overrides.physics)
+                .map_err(|e| format!("overrides: physics: {}", e))?,
+            player: with_overrides(&self.player, &overrides.player)
+                .map_err(|e| format!("overrides: player: {}", e))?,
+            camera: with_overrides(&self.camera, &overrides.camera)
+                .map_err(|e| format!("overrides: camera: {}", e))?,
+        };
+        config.validate().map_err(|e| format!("overrides: {}", e))?;
+        Ok(config)
+    }
+
+    /// What the world is created with
+    pub fn world_config(&self) -> WorldConfig {
+        WorldConfig {
+            integration_parameters: IntegrationParameters {
+                dt: 1.0 / self.physics.steps_per_second,
+                ..IntegrationParameters::default()
+            },
+            player_linear_damping: self.physics.player_linear_damping,
+            gravity_scale: self.physics.gravity_scale,
+            controller: self.player.clone(),
+        }
+    }
+
+    /// Checks for values that parse fine but make no sense, like a negative jump
+    pub fn validate(&self) -> Result<(), String> {
+        let window = &self.window;
+        positive("window.width", window.width as f64)?;
+        positive("window.height", window.height as f64)?;
+
+        let physics = &self.physics;
+        positive("physics.steps_per_second", physics.steps_per_second)?;
+        positive("physics.max_substeps", physics.max_substeps as f64)?;
+        not_negative("physics.gravity_scale", physics.gravity_scale)?;
+        not_negative(
+            "physics.player_linear_damping",
+            physics.player_linear_damping,
+        )?;
+
+        let player = &self.player;
+        positive("player.move_force", player.move_force)?;
+        not_negative("player.air_control", player.air_control)?;
+        positive("player.max_speed", player.max_speed)?;
+        not_negative("player.jump_impulse", player.jump_impulse)?;
+        not_negative("player.coyote_time", player.coyote_time)?;
+        not_negative("player.jump_buffer_time", player.jump_buffer_time)?;
+        if !(0.0..=1.0).contains(&player.jump_cut) {
+            return Err(format!(
+                "player.jump_cut must be between 0 and 1, got {}",
+                player.jump_cut
+            ));
+        }
+        if !(0.0..=std::f64::consts::FRAC_PI_2).contains(&player.max_slope) {
+            return Err(format!(
+                "player.max_slope must be between 0 and pi/2 radians, got {}",
+                player.max_slope
+            ));
+        }
+        positive("player.ground_probe_distance", player.ground_probe_distance)?;
+
+        let camera = &self.camera;
+        positive("camera.zoom", camera.zoom as f64)?;
+        positive("camera.min_zoom", camera.min_zoom as f64)?;
+        if camera.max_zoom < camera.min_zoom {
+            return Err(format!(
+                "camera.max_zoom can't be less than camera.min_zoom, got {} and {}",
+                camera.max_zoom, camera.min_zoom
+            ));
+        }
+        positive("camera.zoom_step", camera.zoom_step as f64)?;
+        not_negative("camera.deadzone", camera.deadzone as f64)?;
+        not_negative("camera.look_ahead", camera.look_ahead as f64)?;
+        positive("camera.follow_speed", camera.follow_speed as f64)?;
+        positive("camera.rotation_speed", camera.rotation_speed as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_match_the_world() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config, Config::default());
+        // exactly, so recordings made before there was a config still play back the same
+        let world = config.world_config();
+        let default = WorldConfig::default();
+        assert_eq!(
+            world.integration_parameters.dt,
+            default.integration_parameters.dt
+        );
+        assert_eq!(world.player_linear_damping, default.player_linear_damping);
+        assert_eq!(world.controller, default.controller);
+    }
+
+    #[test]
+    fn parses_tunables() {
+        let config = Config::parse(
+            r#"
+            [window]
+            width = 640
+            fullscreen = true
+            backend = "gles30"
+
+            [physics]
+            steps_per_second = 120
+            gravity_scale = 2.0
+
+            [player]
+            jump_impulse = 150.0
+
+            [camera]
+            zoom = 4.0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.window.width, 640);
+        assert_eq!(config.window.height, 900);
+        assert!(config.window.fullscreen);
+        assert_eq!(config.window.backend, Some(GlBackend::Gles30));
+        assert_eq!(config.player.jump_impulse, 150.0);
+        assert_eq!(config.player.move_force, 500.0);
+        assert_eq!(config.camera.zoom, 4.0);
+        let world = config.world_config();
+        assert_eq!(world.integration_parameters.dt, 1.0 / 120.0);
+        assert_eq!(world.gravity_scale, 2.0);
+        assert_eq!(world.controller.jump_impulse, 150.0);
+    }
+
+    #[test]
+    fn explains_bad_configs() {
+        let err = Config::parse("[player]\njump_impulse = -200.0").unwrap_err();
+        assert_eq!(err, "player.jump_impulse can't be negative, got -200");
+        let err = Config::parse("[window]\nwidth = 0").unwrap_err();
+        assert_eq!(err, "window.width must be positive, got 0");
+        let err = Config::parse("[camera]\nmin_zoom = 10.0\nmax_zoom = 5.0").unwrap_err();
+        assert!(err.contains("camera.max_zoom"), "{}", err);
+        let err = Config::parse("[player]\njump_impluse = 1.0").unwrap_err();
+        assert!(err.contains("unknown field `jump_impluse`"), "{}", err);
+        let err = Config::parse("[physics]\nsteps_per_second = \"fast\"").unwrap_err();
+        assert!(err.contains("steps_per_second"), "{}", err);
+    }
+
+    #[test]
+    fn levels_override_tunables() {
+        let level = Level::parse(
+            r#"{
+                "spawn": [0, 0],
+                "overrides": {
+                    "physics": { "gravity_scale": 0.5 },
+                    "player": { "jump_impulse": 300 },
+                    "camera": { "planet_up": true }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut base = Config::default();
+        base.window.width = 123;
+        base.player.move_force = 50.0;
+        let config = base.for_level(&level).unwrap();
+        assert_eq!(config.physics.gravity_scale, 0.5);
+        assert_eq!(config.player.jump_impulse, 300.0);
+        // everything else is left alone
+        assert_eq!(config.player.move_force, 50.0);
+        assert_eq!(config.window.width, 123);
+        assert!(config.camera.planet_up);
+
+        // levels without overrides change nothing
+        assert_eq!(base.for_level(&Level::builtin()).unwrap(), base);
+
+        let err = Level::parse(
+            r#"{ "spawn": [0, 0], "overrides": { "player": { "jump_impulse": -1 } } }"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "overrides: player.jump_impulse can't be negative, got -1"
+        );
+        let err =
+            Level::parse(r#"{ "spawn": [0, 0], "overrides": { "window": {} } }"#).unwrap_err();
+        assert!(err.contains("unknown field `window`"), "{}", err);
+        let err = Level::parse(r#"{ "spawn": [0, 0], "overrides": { "camera": { "zom": 2 } } }"#)
+            .unwrap_err();
+        assert!(
+            err.starts_with("overrides: camera: unknown field `zom`"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::CStr;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::gl_shaders::ShaderType;
+
+/// Which kind of GL context to ask for. Shaders are written for GL 3.3 core and turned into
+/// whatever dialect the context understands, see `preprocess`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GlBackend {
+    Gl33,
+    Gles30,
+    Gles20,
+}
+
+impl GlBackend {
+    /// Best first, the order they're tried in when none is asked for
+    pub const ALL: &'static [GlBackend] = &[GlBackend::Gl33, GlBackend::Gles30, GlBackend::Gles20];
+
+    pub fn version(self) -> (u8, u8) {
+        match self {
+            GlBackend::Gl33 => (3, 3),
+            GlBackend::Gles30 => (3, 0),
+            GlBackend::Gles20 => (2, 0),
+        }
+    }
+
+    pub fn is_gles(self) -> bool {
+        self != GlBackend::Gl33
+    }
+
+    /// The line shaders have to start with
+    pub fn glsl_version(self) -> &'static str {
+        match self {
+            GlBackend::Gl33 => "#version 330 core",
+            GlBackend::Gles30 => "#version 300 es",
+            GlBackend::Gles20 => "#version 100",
+        }
+    }
+
+    /// How it's written in config files and on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            GlBackend::Gl33 => "gl33",
+            GlBackend::Gles30 => "gles30",
+            GlBackend::Gles20 => "gles20",
+        }
+    }
+}
+
+impl fmt::Display for GlBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (major, minor) = self.version();
+        let api = if self.is_gles() { "GLES" } else { "GL" };
+        write!(f, "{} {}.{}", api, major, minor)
+    }
+}
+
+impl FromStr for GlBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        GlBackend::ALL
+            .iter()
+            .copied()
+            .find(|backend| backend.name() == s)
+            .ok_or_else(|| format!("Unknown GL backend {}, use gl33, gles30 or gles20", s))
+    }
+}
+
+/// What the context that was actually made can do. GLES 2.0 has no vertex arrays or instancing,
+/// and only some drivers have the debug callback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub backend: GlBackend,
+    pub debug_output: bool,
+    pub vertex_arrays: bool,
+    pub instancing: bool,
+    /// Whether indices can be `u32`, GLES 2.0 needs an extension for that
+    pub uint_indices: bool,
+    /// Whether fragment shaders have `fwidth`, which `shape.frag` smooths its edges with. GLES 2.0
+    /// needs an extension for that too.
+    pub derivatives: bool,
+}
+
+impl Capabilities {
+    /// What `backend` can do when the driver reports `version` and `extensions`
+    pub fn new(backend: GlBackend, version: (u8, u8), extensions: &[&str]) -> Self {
+        let has = |name: &str| extensions.contains(&name);
+        let debug_version = if backend.is_gles() { (3, 2) } else { (4, 3) };
+        let gles20 = backend == GlBackend::Gles20;
+        Capabilities {
+            backend,
+            debug_output: version >= debug_version || has("GL_KHR_debug"),
+            vertex_arrays: !gles20,
+            instancing: !gles20,
+            uint_indices: !gles20 || has("GL_OES_element_index_uint"),
+            derivatives: !gles20 || has("GL_OES_standard_derivatives"),
+        }
+    }
+
+    /// Asks the current context, which has to be a `backend` one
+    pub fn detect(backend: GlBackend) -> Self {
+        let version = gl_string(gl::VERSION)
+            .and_then(|v| parse_version(&v))
+            .unwrap_or_else(|| backend.version());
+        let extensions = extensions(backend);
+        let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+        let mut capabilities = Capabilities::new(backend, version, &extensions);
+        // GLES drivers can have KHR_debug with only the KHR suffixed functions
+        capabilities.debug_output &= gl::DebugMessageCallback::is_loaded();
+        capabilities
+    }
+
+    /// Why the game can't draw with this, if it can't
+    pub fn missing(&self) -> Option<String> {
+        let missing: Vec<&str> = [
+            (self.uint_indices, "GL_OES_element_index_uint"),
+            (self.derivatives, "GL_OES_standard_derivatives"),
+        ]
+        .iter()
+        .filter(|(has, _)| !has)
+        .map(|(_, extension)| *extension)
+        .collect();
+        if missing.is_empty() {
+            None
+        } else {
+            Some(format!("{} without {}", self.backend, missing.join(" or ")))
+        }
+    }
+}
+
+fn gl_string(name: gl::types::GLenum) -> Option<String> {
+    let string = unsafe { gl::GetString(name) };
+    if string.is_null() {
+        return None;
+    }
+    let string = unsafe { CStr::from_ptr(string as *const std::os::raw::c_char) };
+    Some(string.to_string_lossy().into_owned())
+}
+
+fn extensions(backend: GlBackend) -> Vec<String> {
+    if backend == GlBackend::Gles20 {
+        // there's no glGetStringi yet, they're all in one string
+        return gl_string(gl::EXTENSIONS)
+            .map(|all| all.split_whitespace().map(String::from).collect())
+            .unwrap_or_default();
+    }
+    let mut count = 0;
+    unsafe {
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    }
+    (0..count.max(0) as gl::types::GLuint)
+        .filter_map(|i| {
+            let name = unsafe { gl::GetStringi(gl::EXTENSIONS, i) };
+            if name.is_null() {
+                return None;
+            }
+            let name = unsafe { CStr::from_ptr(name as *const std::os::raw::c_char) };
+            Some(name.to_string_lossy().into_owned())
+        })
+        .collect()
+}
+
+/// The major and minor version out of `GL_VERSION`, which is like "4.6 (Core Profile) Mesa" or
+/// "OpenGL ES 3.2 NVIDIA"
+pub fn parse_version(version: &str) -> Option<(u8, u8)> {
+    let number = version
+        .trim_start_matches("OpenGL ES-CM ")
+        .trim_start_matches("OpenGL ES-CL ")
+        .trim_start_matches("OpenGL ES ")
+        .split_whitespace()
+        .next()?;
+    let mut parts = number.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// A shader turned into `backend`'s dialect
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preprocessed {
+    pub source: String,
+    /// The attribute locations GLSL 1.00 can't say with `layout`, to be bound before linking
+    pub attribute_locations: Vec<(String, u32)>,
+}
+
+/// Turns a shader written for GL 3.3 core into `backend`'s dialect. The `#version` line is
+/// replaced, fragment shaders get a default precision on GLES, and for GLES 2.0 `in` and `out`
+/// become `attribute` and `varying`, and the fragment shader's output becomes `gl_FragColor`.
+/// Line numbers stay the same, so compile errors still point at the right line of the file.
+pub fn preprocess(source: &str, stage: ShaderType, backend: GlBackend) -> Preprocessed {
+    let gles20 = backend == GlBackend::Gles20;
+    let mut header = vec![backend.glsl_version().to_string()];
+    if stage == ShaderType::Fragment {
+        let derivatives = ["fwidth", "dFdx", "dFdy"]
+            .iter()
+            .any(|f| source.contains(f));
+        if gles20 && derivatives {
+            header.push("#extension GL_OES_standard_derivatives : enable".to_string());
+        }
+        match backend {
+            GlBackend::Gl33 => {}
+            GlBackend::Gles30 => header.push("precision highp float;".to_string()),
+            // highp is optional in GLES 2.0 fragment shaders
+            GlBackend::Gles20 => header.extend(
+                [
+                    "#ifdef GL_FRAGMENT_PRECISION_HIGH",
+                    "precision highp float;",
+                    "#else",
+                    "precision mediump float;",
+                    "#endif",
+                ]
+                .iter()
+                .map(|line| line.to_string()),
+            ),
+        }
+    }
+    // GLSL 1.00 numbers the line after `#line n` as n + 1, later versions as n
+    header.push(if gles20 { "#line 0" } else { "#line 1" }.to_string());
+
+    let mut attribute_locations = Vec::new();
+    let mut lines = header;
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("#version") {
+            // kept as an empty line so the rest keep their numbers
+            lines.push(String::new());
+        } else if gles20 {
+            lines.push(to_glsl_100(line, stage, &mut attribute_locations));
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    let mut source = lines.join("\n");
+    source.push('\n');
+    Preprocessed {
+        source,
+        attribute_locations,
+    }
+}
+
+/// `layout (location = 2) in` gives back 2 and what follows the `layout`
+fn split_layout(declaration: &str) -> Option<(Option<u32>, &str)> {
+    let rest = declaration.strip_prefix("layout")?.trim_start();
+    let (inside, rest) = rest.strip_prefix('(')?.split_once(')')?;
+    let location = inside
+        .split(',')
+        .filter_map(|q| q.split_once('='))
+        .find(|(name, _)| name.trim() == "location")
+        .and_then(|(_, value)| value.trim().parse().ok());
+    Some((location, rest.trim_start()))
+}
+
+fn to_glsl_100(line: &str, stage: ShaderType, locations: &mut Vec<(String, u32)>) -> String {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let (location, declaration) =
+        split_layout(line.trim_start()).unwrap_or((None, line.trim_start()));
+    let declared_name = |rest: &str| {
+        rest.trim_end()
+            .trim_end_matches(';')
+            .split_whitespace()
+            .last()
+            .map(String::from)
+    };
+    match (stage, declaration.split_once(' ')) {
+        (ShaderType::Vertex, Some(("in", rest))) => {
+            if let (Some(location), Some(name)) = (location, declared_name(rest)) {
+                locations.push((name, location));
+            }
+            format!("{}attribute {}", indent, rest)
+        }
+        (ShaderType::Vertex, Some(("out", rest))) | (ShaderType::Fragment, Some(("in", rest))) => {
+            format!("{}varying {}", indent, rest)
+        }
+        (ShaderType::Fragment, Some(("out", rest))) => match declared_name(rest) {
+            Some(name) => format!("{}#define {} gl_FragColor", indent, name),
+            None => line.to_string(),
+        },
+        _ => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERTEX: &str = "#version 330 core
+
+layout (location = 0) in vec2 Position;
+layout(location=3) in vec4 VertexColor;
+out vec4 color;
+
+void main()
+{
+    color = VertexColor;
+    gl_Position = vec4(Position, 0.0, 1.0);
+}
+";
+
+    const FRAGMENT: &str = "#version 330 core
+
+out vec4 Color;
+in vec4 color;
+
+void main()
+{
+    float pixel = fwidth(color.a);
+    Color = color * pixel;
+}
+";
+
+    /// The source after the injected header, which should line up with the original
+    fn body(preprocessed: &Preprocessed) -> Vec<&str> {
+        let lines: Vec<&str> = preprocessed.source.lines().collect();
+        let start = lines.iter().position(|l| l.starts_with("#line")).unwrap();
+        lines[start + 1..].to_vec()
+    }
+
+    #[test]
+    fn gl33_only_moves_the_version() {
+        let out = preprocess(VERTEX, ShaderType::Vertex, GlBackend::Gl33);
+        assert!(out.source.starts_with("#version 330 core\n#line 1\n"));
+        assert_eq!(body(&out).len(), VERTEX.lines().count());
+        assert_eq!(body(&out)[2], "layout (location = 0) in vec2 Position;");
+        assert!(out.attribute_locations.is_empty());
+    }
+
+    #[test]
+    fn gles30_adds_precision_to_fragment_shaders() {
+        let out = preprocess(FRAGMENT, ShaderType::Fragment, GlBackend::Gles30);
+        assert!(out
+            .source
+            .starts_with("#version 300 es\nprecision highp float;\n#line 1\n"));
+        assert_eq!(body(&out)[2], "out vec4 Color;");
+        let vertex = preprocess(VERTEX, ShaderType::Vertex, GlBackend::Gles30);
+        assert!(!vertex.source.contains("precision"));
+    }
+
+    #[test]
+    fn gles20_uses_attributes_and_varyings() {
+        let out = preprocess(VERTEX, ShaderType::Vertex, GlBackend::Gles20);
+        let lines = body(&out);
+        assert_eq!(lines.len(), VERTEX.lines().count());
+        assert_eq!(lines[0], "");
+        assert_eq!(lines[2], "attribute vec2 Position;");
+        assert_eq!(lines[3], "attribute vec4 VertexColor;");
+        assert_eq!(lines[4], "varying vec4 color;");
+        assert_eq!(lines[8], "    color = VertexColor;");
+        assert_eq!(
+            out.attribute_locations,
+            vec![("Position".to_string(), 0), ("VertexColor".to_string(), 3)]
+        );
+
+        let out = preprocess(FRAGMENT, ShaderType::Fragment, GlBackend::Gles20);
+        assert!(out
+            .source
+            .starts_with("#version 100\n#extension GL_OES_standard_derivatives : enable\n#ifdef"));
+        assert!(out.source.contains("precision mediump float;"));
+        let lines = body(&out);
+        assert_eq!(lines[2], "#define Color gl_FragColor");
+        assert_eq!(lines[3], "varying vec4 color;");
+    }
+
+    #[test]
+    fn builtin_shaders_keep_their_attributes() {
+        let out = preprocess(
+            include_str!("../assets/shaders/round_rect.vert"),
+            ShaderType::Vertex,
+            GlBackend::Gles20,
+        );
+        assert_eq!(out.attribute_locations.len(), 8);
+        assert_eq!(out.attribute_locations[7], ("OutlineColor".to_string(), 7));
+        assert!(!out.source.contains("layout"));
+    }
+
+    #[test]
+    fn parses_backends_and_versions() {
+        assert_eq!("gles30".parse(), Ok(GlBackend::Gles30));
+        assert!("gl46".parse::<GlBackend>().unwrap_err().contains("gl46"));
+        assert_eq!(GlBackend::Gles20.to_string(), "GLES 2.0");
+        assert_eq!(parse_version("4.6 (Core Profile) Mesa 23.1"), Some((4, 6)));
+        assert_eq!(parse_version("OpenGL ES 3.2 NVIDIA 535"), Some((3, 2)));
+        assert_eq!(parse_version("OpenGL ES 2.0"), Some((2, 0)));
+        assert_eq!(parse_version("garbage"), None);
+    }
+
+    #[test]
+    fn knows_what_each_backend_can_do() {
+        let gl = Capabilities::new(GlBackend::Gl33, (4, 6), &[]);
+        assert!(gl.debug_output && gl.instancing && gl.vertex_arrays);
+        assert!(!Capabilities::new(GlBackend::Gl33, (3, 3), &[]).debug_output);
+        assert!(Capabilities::new(GlBackend::Gl33, (3, 3), &["GL_KHR_debug"]).debug_output);
+
+        let es2 = Capabilities::new(GlBackend::Gles20, (2, 0), &[]);
+        assert!(!es2.debug_output && !es2.instancing && !es2.vertex_arrays);
+        assert_eq!(
+            es2.missing().unwrap(),
+            "GLES 2.0 without GL_OES_element_index_uint or GL_OES_standard_derivatives"
+        );
+        let es2 = Capabilities::new(GlBackend::Gles20, (2, 0), &["GL_OES_element_index_uint"]);
+        assert!(!es2.derivatives);
+        assert_eq!(
+            es2.missing().unwrap(),
+            "GLES 2.0 without GL_OES_standard_derivatives"
+        );
+        let extensions = ["GL_OES_element_index_uint", "GL_OES_standard_derivatives"];
+        let es2 = Capabilities::new(GlBackend::Gles20, (2, 0), &extensions);
+        assert_eq!(es2.missing(), None);
+        assert!(Capabilities::new(GlBackend::Gles30, (3, 2), &[]).debug_output);
+    }
+}
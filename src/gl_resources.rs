@@ -0,0 +1,168 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Every kind of GL object something in here owns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    Buffer,
+    VertexArray,
+    Texture,
+    Shader,
+    Program,
+}
+
+impl ResourceKind {
+    pub const ALL: &'static [ResourceKind] = &[
+        ResourceKind::Buffer,
+        ResourceKind::VertexArray,
+        ResourceKind::Texture,
+        ResourceKind::Shader,
+        ResourceKind::Program,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+static LIVE: [AtomicUsize; 5] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+/// Should be called whenever a GL object is created, and `untrack` when it's deleted. The RAII
+/// types below do this themselves.
+pub fn track(kind: ResourceKind) {
+    LIVE[kind.index()].fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn untrack(kind: ResourceKind) {
+    LIVE[kind.index()].fetch_sub(1, Ordering::Relaxed);
+}
+
+/// How many GL objects of each kind currently exist
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LiveCounts([usize; 5]);
+
+impl LiveCounts {
+    pub fn now() -> Self {
+        let mut counts = [0; 5];
+        for kind in ResourceKind::ALL.iter() {
+            counts[kind.index()] = LIVE[kind.index()].load(Ordering::Relaxed);
+        }
+        LiveCounts(counts)
+    }
+
+    pub fn get(&self, kind: ResourceKind) -> usize {
+        self.0[kind.index()]
+    }
+
+    pub fn total(&self) -> usize {
+        self.0.iter().sum()
+    }
+}
+
+impl fmt::Display for LiveCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for kind in ResourceKind::ALL.iter() {
+            if self.get(*kind) == 0 {
+                continue;
+            }
+            if !first {
+                write!(f, ", ")?;
+            }
+            first = false;
+            write!(f, "{} {:?}", self.get(*kind), kind)?;
+        }
+        if first {
+            write!(f, "nothing")?;
+        }
+        Ok(())
+    }
+}
+
+/// Meant to be called right before the GL context is destroyed, when everything should already
+/// have been dropped
+pub fn check_leaks() -> Result<(), String> {
+    let counts = LiveCounts::now();
+    if counts.total() == 0 {
+        Ok(())
+    } else {
+        Err(format!("Leaked GL objects: {}", counts))
+    }
+}
+
+/// Generates the GL names and deletes them again when dropped, for the kinds of objects that are
+/// all created and deleted the same way
+macro_rules! gl_object {
+    ($name:ident, $kind:expr, $gen:path, $delete:path) => {
+        pub struct $name {
+            id: gl::types::GLuint,
+        }
+
+        impl $name {
+            /// Needs a current GL context
+            #[allow(clippy::new_without_default)]
+            pub fn new() -> Self {
+                let mut id = 0;
+                unsafe {
+                    $gen(1, &mut id);
+                }
+                track($kind);
+                $name { id }
+            }
+
+            pub fn id(&self) -> gl::types::GLuint {
+                self.id
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                unsafe {
+                    $delete(1, &self.id);
+                }
+                untrack($kind);
+            }
+        }
+    };
+}
+
+gl_object!(
+    GlBuffer,
+    ResourceKind::Buffer,
+    gl::GenBuffers,
+    gl::DeleteBuffers
+);
+gl_object!(
+    GlVertexArray,
+    ResourceKind::VertexArray,
+    gl::GenVertexArrays,
+    gl::DeleteVertexArrays
+);
+gl_object!(
+    GlTexture,
+    ResourceKind::Texture,
+    gl::GenTextures,
+    gl::DeleteTextures
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_counts() {
+        let mut counts = LiveCounts::default();
+        assert_eq!(counts.to_string(), "nothing");
+        counts.0[ResourceKind::Buffer.index()] = 2;
+        counts.0[ResourceKind::Program.index()] = 1;
+        assert_eq!(counts.total(), 3);
+        assert_eq!(counts.get(ResourceKind::Program), 1);
+        assert_eq!(counts.to_string(), "2 Buffer, 1 Program");
+    }
+}
@@ -1,94 +1,94 @@
 use gl;
-use nalgebra as na;
 use std;
+use std::collections::HashMap;
 use std::ffi::CString;
 
-// based off of https://github.com/Nercury/rust-and-opengl-lessons/blob/master/lesson-03/src/render_gl.rs
+use crate::gl_backend::{preprocess, GlBackend};
+use crate::gl_resources::{track, untrack, ResourceKind};
+use crate::gl_uniforms::{check_uniform, uniform_name, Uniform, UniformInfo};
 
-macro_rules! shader_inline {
-    ($vert_program:literal , $frag_program:literal) => {
-        ShaderProgram::from_shaders(&[
-            Shader::from_source($vert_program, ShaderType::Vertex).unwrap(),
-            Shader::from_source($frag_program, ShaderType::Fragment).unwrap(),
-        ])
-        .unwrap()
-    };
-}
+// based off of https://github.com/Nercury/rust-and-opengl-lessons/blob/master/lesson-03/src/render_gl.rs
 
-macro_rules! shader {
-    ($vert_program:literal , $frag_program:literal) => {
-        ShaderProgram::from_shaders(&[
-            Shader::from_source(include_str!($vert_program), ShaderType::Vertex).unwrap(),
-            Shader::from_source(include_str!($frag_program), ShaderType::Fragment).unwrap(),
-        ])
-        .unwrap()
-    };
-}
 pub struct ShaderProgram {
     id: gl::types::GLuint,
+    /// Every active uniform by name, found once the program is linked
+    uniforms: HashMap<String, UniformInfo>,
 }
 
 impl ShaderProgram {
-    fn get_location(&self, name: &str) -> i32 {
-        unsafe {
-            gl::GetUniformLocation(
-                self.id(),
-                CString::new(name).unwrap().as_bytes_with_nul().as_ptr() as *const i8,
-            )
-        }
-    }
-    // read only reference might be the wrong thing in this case, as it is modifying GPU data that
-    // the shader "owns"
-    pub fn write_mat4(&self, name: &str, mat: &na::Matrix4<f32>) {
+    /// Writes `value` to the uniform `name` of this program, which has to be in use. Fails if
+    /// there's no such uniform or `value` isn't the right type for it.
+    pub fn set_uniform<T: Uniform + ?Sized>(&self, name: &str, value: &T) -> Result<(), String> {
+        let location = check_uniform(name, self.uniforms.get(name), value)?;
         unsafe {
-            gl::UniformMatrix4fv(
-                self.get_location(name),
-                1,
-                gl::FALSE,
-                mat.as_slice().as_ptr(),
-            );
+            value.write(location);
         }
+        Ok(())
     }
-    pub fn write_mat3(&self, name: &str, mat: &na::Matrix3<f32>) {
-        unsafe {
-            gl::UniformMatrix3fv(
-                self.get_location(name),
-                1,
-                gl::FALSE,
-                mat.as_slice().as_ptr(),
-            );
-        }
+
+    pub fn uniform(&self, name: &str) -> Option<&UniformInfo> {
+        self.uniforms.get(name)
     }
-    pub fn write_point2(&self, name: &str, point: &na::Point2<f32>) {
+
+    /// Asks GL for every uniform the linked program actually uses
+    fn find_uniforms(program_id: gl::types::GLuint) -> HashMap<String, UniformInfo> {
+        let mut count: gl::types::GLint = 0;
+        let mut max_name_len: gl::types::GLint = 0;
         unsafe {
-            gl::Uniform2fv(
-                self.get_location(name),
-                1,
-                [point.x, point.y].as_ptr(), // TODO figure out how to slice into pointer without copy like the matrices
-            );
+            gl::GetProgramiv(program_id, gl::ACTIVE_UNIFORMS, &mut count);
+            gl::GetProgramiv(program_id, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_name_len);
         }
-    }
-    pub fn write_vec2(&self, name: &str, vec: &na::Vector2<f32>) {
-        unsafe {
-            gl::Uniform2fv(
-                self.get_location(name),
-                1,
-                [vec.x, vec.y].as_ptr(),
+        let mut uniforms = HashMap::new();
+        for index in 0..count as gl::types::GLuint {
+            let mut name = vec![0u8; max_name_len.max(1) as usize];
+            let mut name_len: gl::types::GLsizei = 0;
+            let mut size: gl::types::GLint = 0;
+            let mut gl_type: gl::types::GLenum = 0;
+            unsafe {
+                gl::GetActiveUniform(
+                    program_id,
+                    index,
+                    name.len() as gl::types::GLsizei,
+                    &mut name_len,
+                    &mut size,
+                    &mut gl_type,
+                    name.as_mut_ptr() as *mut gl::types::GLchar,
+                );
+            }
+            name.truncate(name_len as usize);
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let c_name = CString::new(name.as_str()).unwrap();
+            let location = unsafe { gl::GetUniformLocation(program_id, c_name.as_ptr()) };
+            uniforms.insert(
+                uniform_name(&name).to_string(),
+                UniformInfo {
+                    location,
+                    gl_type,
+                    size,
+                },
             );
         }
+        uniforms
     }
-    pub fn write_float(&self, name: &str, f: f32) {
-        unsafe {
-            gl::Uniform1f(self.get_location(name), f);
-        }
-    }
+
     pub fn from_shaders(shaders: &[Shader]) -> Result<ShaderProgram, String> {
         let program_id = unsafe { gl::CreateProgram() };
+        track(ResourceKind::Program);
+        // deletes the program again if linking fails
+        let mut program = ShaderProgram {
+            id: program_id,
+            uniforms: HashMap::new(),
+        };
 
         for shader in shaders {
             unsafe {
                 gl::AttachShader(program_id, shader.id());
             }
+            for (name, location) in shader.attribute_locations.iter() {
+                unsafe {
+                    gl::BindAttribLocation(program_id, *location, name.as_ptr());
+                }
+            }
         }
 
         unsafe {
@@ -125,8 +125,9 @@ impl ShaderProgram {
                 gl::DetachShader(program_id, shader.id());
             }
         }
+        program.uniforms = Self::find_uniforms(program_id);
 
-        Ok(ShaderProgram { id: program_id })
+        Ok(program)
     }
 
     pub fn id(&self) -> gl::types::GLuint {
@@ -145,9 +146,11 @@ impl Drop for ShaderProgram {
         unsafe {
             gl::DeleteProgram(self.id);
         }
+        untrack(ResourceKind::Program);
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ShaderType {
     Vertex,
     Fragment,
@@ -164,12 +167,37 @@ impl ShaderType {
 
 pub struct Shader {
     id: gl::types::GLuint,
+    /// Bound when the shader is linked, for dialects that can't give them in the source
+    attribute_locations: Vec<(CString, gl::types::GLuint)>,
 }
 
 impl Shader {
+    /// Compiles GLSL written for GL 3.3 core after turning it into `backend`'s dialect, see
+    /// `gl_backend::preprocess`
+    pub fn from_glsl(
+        source: &str,
+        shader_kind: ShaderType,
+        backend: GlBackend,
+    ) -> Result<Shader, String> {
+        let preprocessed = preprocess(source, shader_kind, backend);
+        let mut shader = Shader::from_source(&preprocessed.source, shader_kind)?;
+        shader.attribute_locations = preprocessed
+            .attribute_locations
+            .into_iter()
+            .map(|(name, location)| (CString::new(name).unwrap(), location))
+            .collect();
+        Ok(shader)
+    }
+
     pub fn from_source(source_str: &str, shader_kind: ShaderType) -> Result<Shader, String> {
         let kind = shader_kind.to_gl_type();
         let id = unsafe { gl::CreateShader(kind) };
+        track(ResourceKind::Shader);
+        // deletes the shader again if compiling fails
+        let shader = Shader {
+            id,
+            attribute_locations: Vec::new(),
+        };
         let source = &CString::new(source_str).unwrap();
         unsafe {
             gl::ShaderSource(id, 1, &source.as_ptr(), std::ptr::null());
@@ -201,7 +229,7 @@ impl Shader {
             return Err(error.to_string_lossy().into_owned());
         }
 
-        Ok(Shader { id })
+        Ok(shader)
     }
 
     fn id(&self) -> gl::types::GLuint {
@@ -214,6 +242,7 @@ impl Drop for Shader {
         unsafe {
             gl::DeleteShader(self.id);
         }
+        untrack(ResourceKind::Shader);
     }
 }
 
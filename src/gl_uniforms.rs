@@ -0,0 +1,265 @@
+use gl::types::{GLenum, GLint, GLsizei};
+
+use crate::render::Color;
+
+/// A texture unit, for setting `sampler2D` and the like
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sampler(pub i32);
+
+/// Something that can be written to a uniform, see `ShaderProgram::set_uniform`. Slices write
+/// to arrays, starting at their first element.
+pub trait Uniform {
+    /// Whether this can be written to a uniform GL reports as `gl_type`, like `gl::FLOAT_VEC2`
+    fn fits(gl_type: GLenum) -> bool;
+
+    /// How many array elements it fills
+    fn count(&self) -> usize {
+        1
+    }
+
+    /// Writes to `location` of the program in use
+    ///
+    /// # Safety
+    /// `location` has to belong to the program in use and have room for `count` values that fit
+    unsafe fn write(&self, location: GLint);
+}
+
+macro_rules! impl_uniform {
+    ($t:ty, [$($gl_type:expr),+], |$location:ident, $count:ident, $values:ident| $write:expr) => {
+        impl Uniform for $t {
+            fn fits(gl_type: GLenum) -> bool {
+                [$($gl_type),+].contains(&gl_type)
+            }
+
+            unsafe fn write(&self, location: GLint) {
+                std::slice::from_ref(self).write(location)
+            }
+        }
+
+        impl Uniform for [$t] {
+            fn fits(gl_type: GLenum) -> bool {
+                <$t>::fits(gl_type)
+            }
+
+            fn count(&self) -> usize {
+                self.len()
+            }
+
+            unsafe fn write(&self, $location: GLint) {
+                let $count = self.len() as GLsizei;
+                let $values = self;
+                $write
+            }
+        }
+    };
+}
+
+// nalgebra's vectors and matrices are plain arrays of floats in column major order, just like GL
+// wants them, so slices of them can be passed straight through
+impl_uniform!(f32, [gl::FLOAT], |location, count, values| {
+    gl::Uniform1fv(location, count, values.as_ptr())
+});
+impl_uniform!(i32, [gl::INT], |location, count, values| {
+    gl::Uniform1iv(location, count, values.as_ptr())
+});
+impl_uniform!(bool, [gl::BOOL], |location, count, values| {
+    let ints: Vec<i32> = values.iter().map(|b| *b as i32).collect();
+    gl::Uniform1iv(location, count, ints.as_ptr())
+});
+impl_uniform!(
+    Sampler,
+    [
+        gl::SAMPLER_2D,
+        gl::SAMPLER_3D,
+        gl::SAMPLER_CUBE,
+        gl::SAMPLER_2D_SHADOW,
+        gl::SAMPLER_2D_ARRAY
+    ],
+    |location, count, values| gl::Uniform1iv(location, count, values.as_ptr() as *const i32)
+);
+impl_uniform!(
+    na::Vector2<f32>,
+    [gl::FLOAT_VEC2],
+    |location, count, values| gl::Uniform2fv(location, count, values.as_ptr() as *const f32)
+);
+impl_uniform!(
+    na::Point2<f32>,
+    [gl::FLOAT_VEC2],
+    |location, count, values| gl::Uniform2fv(location, count, values.as_ptr() as *const f32)
+);
+impl_uniform!(
+    na::Vector3<f32>,
+    [gl::FLOAT_VEC3],
+    |location, count, values| gl::Uniform3fv(location, count, values.as_ptr() as *const f32)
+);
+impl_uniform!(
+    na::Vector4<f32>,
+    [gl::FLOAT_VEC4],
+    |location, count, values| gl::Uniform4fv(location, count, values.as_ptr() as *const f32)
+);
+impl_uniform!(
+    na::Vector2<i32>,
+    [gl::INT_VEC2],
+    |location, count, values| gl::Uniform2iv(location, count, values.as_ptr() as *const i32)
+);
+impl_uniform!(Color, [gl::FLOAT_VEC4], |location, count, values| {
+    gl::Uniform4fv(location, count, values.as_ptr() as *const f32)
+});
+impl_uniform!(
+    na::Matrix3<f32>,
+    [gl::FLOAT_MAT3],
+    |location, count, values| gl::UniformMatrix3fv(
+        location,
+        count,
+        gl::FALSE,
+        values.as_ptr() as *const f32
+    )
+);
+impl_uniform!(
+    na::Matrix4<f32>,
+    [gl::FLOAT_MAT4],
+    |location, count, values| gl::UniformMatrix4fv(
+        location,
+        count,
+        gl::FALSE,
+        values.as_ptr() as *const f32
+    )
+);
+
+/// A uniform as `glGetActiveUniform` describes it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UniformInfo {
+    pub location: GLint,
+    pub gl_type: GLenum,
+    /// How many elements, 1 unless it's an array
+    pub size: GLint,
+}
+
+/// How GLSL spells a type, for error messages
+pub fn glsl_type_name(gl_type: GLenum) -> String {
+    match gl_type {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::BOOL => "bool",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        _ => return format!("type {:#x}", gl_type),
+    }
+    .to_string()
+}
+
+/// Where to write `value` to the uniform `name`, if it exists and `value` fits it
+pub fn check_uniform<T: Uniform + ?Sized>(
+    name: &str,
+    info: Option<&UniformInfo>,
+    value: &T,
+) -> Result<GLint, String> {
+    let info = info.ok_or_else(|| {
+        format!(
+            "There's no uniform {}, or it's never used and was optimized out",
+            name
+        )
+    })?;
+    if !T::fits(info.gl_type) {
+        return Err(format!(
+            "Uniform {} is a {}, which a {} can't be written to",
+            name,
+            glsl_type_name(info.gl_type),
+            std::any::type_name::<T>()
+        ));
+    }
+    if value.count() > info.size as usize {
+        return Err(format!(
+            "Uniform {} has room for {} values, not {}",
+            name,
+            info.size,
+            value.count()
+        ));
+    }
+    Ok(info.location)
+}
+
+/// `glGetActiveUniform` calls arrays `name[0]`, but they're set by just `name`
+pub fn uniform_name(active_name: &str) -> &str {
+    active_name.strip_suffix("[0]").unwrap_or(active_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_types_and_sizes() {
+        let vec2 = UniformInfo {
+            location: 3,
+            gl_type: gl::FLOAT_VEC2,
+            size: 1,
+        };
+        assert_eq!(
+            check_uniform("offset", Some(&vec2), &na::Vector2::new(1.0, 2.0)),
+            Ok(3)
+        );
+        assert_eq!(
+            check_uniform("offset", Some(&vec2), &na::Point2::new(1.0, 2.0)),
+            Ok(3)
+        );
+        let err = check_uniform("offset", Some(&vec2), &1.0f32).unwrap_err();
+        assert_eq!(
+            err,
+            "Uniform offset is a vec2, which a f32 can't be written to"
+        );
+        let err = check_uniform("ofset", None, &1.0f32).unwrap_err();
+        assert!(err.contains("no uniform ofset"), "{}", err);
+
+        let colors = UniformInfo {
+            location: 0,
+            gl_type: gl::FLOAT_VEC4,
+            size: 4,
+        };
+        let palette: [Color; 3] = [[1.0; 4], [0.5; 4], [0.0; 4]];
+        assert_eq!(check_uniform("palette", Some(&colors), &palette[..]), Ok(0));
+        assert_eq!(
+            check_uniform("palette", Some(&colors), &na::Vector4::zeros()),
+            Ok(0)
+        );
+        let err = check_uniform("palette", Some(&colors), &[[0.0f32; 4]; 5][..]).unwrap_err();
+        assert_eq!(err, "Uniform palette has room for 4 values, not 5");
+
+        let texture = UniformInfo {
+            location: 1,
+            gl_type: gl::SAMPLER_2D,
+            size: 1,
+        };
+        assert_eq!(check_uniform("image", Some(&texture), &Sampler(0)), Ok(1));
+        assert!(check_uniform("image", Some(&texture), &0i32).is_err());
+        assert!(bool::fits(gl::BOOL) && !bool::fits(gl::INT));
+    }
+
+    #[test]
+    fn names_arrays_without_the_index() {
+        assert_eq!(uniform_name("lights[0]"), "lights");
+        assert_eq!(uniform_name("camera"), "camera");
+        assert_eq!(uniform_name("light.color"), "light.color");
+    }
+
+    #[test]
+    fn values_are_tightly_packed() {
+        // so slices of them can be handed to GL as plain floats
+        assert_eq!(std::mem::size_of::<[na::Vector3<f32>; 2]>(), 6 * 4);
+        assert_eq!(std::mem::size_of::<[na::Matrix4<f32>; 2]>(), 32 * 4);
+        assert_eq!(std::mem::size_of::<[Sampler; 2]>(), 2 * 4);
+    }
+}
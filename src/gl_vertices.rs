@@ -1,188 +1,735 @@
+use gl::types::{GLenum, GLint, GLuint};
+use std::ops::Range;
+
+use crate::gl_backend::Capabilities;
+use crate::gl_resources::{GlBuffer, GlVertexArray};
+
+/// How the shader sees an attribute's values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttribKind {
+    /// As floats, integers are converted as they are, so 255u8 is 255.0
+    Float,
+    /// As floats, integers are mapped to 0 to 1 (or -1 to 1 if signed), so 255u8 is 1.0
+    Normalized,
+    /// As integers, for `int` and `ivec` attributes in the shader
+    Integer,
+}
+
+/// Where one field of a vertex is in the buffer, and which attribute location it goes to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct VertexAttrib {
-    // TODO figure out a way to automatically calculate the gl_type and size of 3 components based
-    // on a type parameter
-    gl_type: gl::types::GLenum,
-    size: gl::types::GLint,
-    components: gl::types::GLint, // must be between 1 and 4
+    pub location: GLuint,
+    pub gl_type: GLenum,
+    /// Between 1 and 4
+    pub components: GLint,
+    pub kind: AttribKind,
+    /// In bytes, from the start of the vertex
+    pub offset: usize,
+    /// In bytes
+    pub size: usize,
 }
 
-pub mod vertex_attribs {
-    use super::*;
-    pub const POINT3_F32: VertexAttrib = VertexAttrib {
-        gl_type: gl::FLOAT,
-        size: 3 * std::mem::size_of::<f32>() as i32,
-        components: 3,
-    };
-    pub const POINT2_F32: VertexAttrib = VertexAttrib {
-        gl_type: gl::FLOAT,
-        size: 2 * std::mem::size_of::<f32>() as i32,
-        components: 2,
-    };
-    pub const POINT2_F64: VertexAttrib = VertexAttrib {
-        gl_type: gl::FLOAT,
-        size: 2 * std::mem::size_of::<f64>() as i32,
-        components: 2,
+/// A type that can be a field of a vertex, as a GL type and how many of them
+pub trait AttribType {
+    const GL_TYPE: GLenum;
+    const COMPONENTS: GLint;
+}
+
+macro_rules! attrib_types {
+    ($($t:ty => $gl_type:expr, $components:expr;)*) => {
+        $(
+            impl AttribType for $t {
+                const GL_TYPE: GLenum = $gl_type;
+                const COMPONENTS: GLint = $components;
+            }
+        )*
     };
-    pub const VECTOR2_F32: VertexAttrib = VertexAttrib {
-        gl_type: gl::FLOAT,
-        size: 2 * std::mem::size_of::<f32>() as i32,
-        components: 2,
+}
+
+attrib_types! {
+    f32 => gl::FLOAT, 1;
+    [f32; 2] => gl::FLOAT, 2;
+    [f32; 3] => gl::FLOAT, 3;
+    [f32; 4] => gl::FLOAT, 4;
+    na::Vector2<f32> => gl::FLOAT, 2;
+    na::Vector3<f32> => gl::FLOAT, 3;
+    na::Vector4<f32> => gl::FLOAT, 4;
+    na::Point2<f32> => gl::FLOAT, 2;
+    na::Point3<f32> => gl::FLOAT, 3;
+    f64 => gl::DOUBLE, 1;
+    na::Vector2<f64> => gl::DOUBLE, 2;
+    na::Point2<f64> => gl::DOUBLE, 2;
+    i32 => gl::INT, 1;
+    [i32; 2] => gl::INT, 2;
+    u32 => gl::UNSIGNED_INT, 1;
+    u16 => gl::UNSIGNED_SHORT, 1;
+    [u16; 2] => gl::UNSIGNED_SHORT, 2;
+    u8 => gl::UNSIGNED_BYTE, 1;
+    [u8; 4] => gl::UNSIGNED_BYTE, 4;
+    i8 => gl::BYTE, 1;
+    [i8; 4] => gl::BYTE, 4;
+}
+
+fn is_integer(gl_type: GLenum) -> bool {
+    !matches!(gl_type, gl::FLOAT | gl::DOUBLE)
+}
+
+impl VertexAttrib {
+    /// The attribute for a field of type `F`. `field` is only there to infer `F` from, it isn't
+    /// read.
+    pub fn of_field<F: AttribType>(
+        _field: *const F,
+        location: GLuint,
+        offset: usize,
+        kind: AttribKind,
+    ) -> Self {
+        VertexAttrib {
+            location,
+            gl_type: F::GL_TYPE,
+            components: F::COMPONENTS,
+            kind,
+            offset,
+            size: std::mem::size_of::<F>(),
+        }
+    }
+}
+
+/// A `#[repr(C)]` struct that can go in a `VertexData`. Implement it with `impl_vertex!`.
+pub trait Vertex: Copy {
+    fn attributes() -> Vec<VertexAttrib>;
+}
+
+/// Implements `Vertex` for a struct, from the attribute location of each of its fields, which
+/// can be followed by `normalized` or `integer` (see `AttribKind`):
+///
+/// ```ignore
+/// impl_vertex!(Particle {
+///     position: 0,
+///     color: 1 normalized,
+/// });
+/// ```
+///
+/// Every field has to be listed, `check_layout` fails on any that are left out.
+#[macro_export]
+macro_rules! impl_vertex {
+    (@kind) => { $crate::gl_vertices::AttribKind::Float };
+    (@kind normalized) => { $crate::gl_vertices::AttribKind::Normalized };
+    (@kind integer) => { $crate::gl_vertices::AttribKind::Integer };
+    ($t:ty { $($field:ident : $location:literal $($kind:ident)?),* $(,)? }) => {
+        impl $crate::gl_vertices::Vertex for $t {
+            fn attributes() -> Vec<$crate::gl_vertices::VertexAttrib> {
+                let vertex = std::mem::MaybeUninit::<$t>::uninit();
+                let base = vertex.as_ptr();
+                vec![$({
+                    // only the address is taken, nothing uninitialized is read
+                    let field = unsafe { std::ptr::addr_of!((*base).$field) };
+                    $crate::gl_vertices::VertexAttrib::of_field(
+                        field,
+                        $location,
+                        field as usize - base as usize,
+                        $crate::impl_vertex!(@kind $($kind)?),
+                    )
+                }),*]
+            }
+        }
     };
 }
 
-pub struct VertexData<T> {
-    vao: gl::types::GLuint,
-    vbo: gl::types::GLuint,
-    ebo: gl::types::GLuint,
+/// Checks that `V`'s attributes make sense: every field covered without overlapping, so they
+/// add up to the stride, distinct locations, and only integers normalized or kept as integers
+pub fn check_layout<V: Vertex>() -> Result<(), String> {
+    let mut attributes = V::attributes();
+    attributes.sort_by_key(|a| a.offset);
+    let mut end = 0;
+    for (i, a) in attributes.iter().enumerate() {
+        if !(1..=4).contains(&a.components) {
+            return Err(format!(
+                "location {} has {} components, it can only have 1 to 4",
+                a.location, a.components
+            ));
+        }
+        if a.offset < end {
+            return Err(format!(
+                "location {} overlaps the one before it",
+                a.location
+            ));
+        }
+        if a.offset > end {
+            return Err(format!(
+                "there's {} bytes of padding before location {}",
+                a.offset - end,
+                a.location
+            ));
+        }
+        end = a.offset + a.size;
+        if attributes[..i].iter().any(|b| b.location == a.location) {
+            return Err(format!("location {} is used twice", a.location));
+        }
+        if a.kind != AttribKind::Float && !is_integer(a.gl_type) {
+            return Err(format!(
+                "location {} is {:?}, but only integers can be",
+                a.location, a.kind
+            ));
+        }
+    }
+    let stride = std::mem::size_of::<V>();
+    if end != stride {
+        return Err(format!(
+            "the attributes add up to {} bytes, but each vertex is {}",
+            end, stride
+        ));
+    }
+    Ok(())
+}
+
+/// No per-instance attributes, for `VertexData` that isn't drawn instanced
+impl Vertex for () {
+    fn attributes() -> Vec<VertexAttrib> {
+        Vec::new()
+    }
+}
+
+/// Checks that the attributes of a vertex and its instances don't use the same locations
+pub fn check_instance_layout<T: Vertex, I: Vertex>() -> Result<(), String> {
+    check_layout::<T>()?;
+    check_layout::<I>().map_err(|e| format!("instance: {}", e))?;
+    let vertex = T::attributes();
+    for a in I::attributes() {
+        if vertex.iter().any(|b| b.location == a.location) {
+            return Err(format!(
+                "location {} is used by both the vertex and the instance",
+                a.location
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// How often a buffer's contents are expected to change, a hint to the driver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Set once and drawn many times
+    Static,
+    /// Changed now and then, often only a few elements at a time
+    Dynamic,
+    /// Replaced every frame. The old storage is orphaned whenever anything changes, so the
+    /// driver can hand out fresh memory instead of waiting for the GPU to finish drawing from it.
+    Stream,
+}
+
+impl BufferUsage {
+    fn to_gl(self) -> GLenum {
+        match self {
+            BufferUsage::Static => gl::STATIC_DRAW,
+            BufferUsage::Dynamic => gl::DYNAMIC_DRAW,
+            BufferUsage::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+/// What the vertices make up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Primitive {
+    Points,
+    Lines,
+    LineStrip,
+    LineLoop,
+    Triangles,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl Primitive {
+    fn to_gl(self) -> GLenum {
+        match self {
+            Primitive::Points => gl::POINTS,
+            Primitive::Lines => gl::LINES,
+            Primitive::LineStrip => gl::LINE_STRIP,
+            Primitive::LineLoop => gl::LINE_LOOP,
+            Primitive::Triangles => gl::TRIANGLES,
+            Primitive::TriangleStrip => gl::TRIANGLE_STRIP,
+            Primitive::TriangleFan => gl::TRIANGLE_FAN,
+        }
+    }
+}
+
+/// Buffers start with room for at least this many elements
+const MIN_CAPACITY: usize = 64;
+
+/// What has to happen to get a buffer's contents onto the GPU
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Upload {
+    Nothing,
+    /// Make new storage for this many elements and fill it, because the old storage is too
+    /// small or being orphaned
+    Everything {
+        capacity: usize,
+    },
+    /// Only these elements changed
+    Range(Range<usize>),
+}
+
+/// Keeps track of how big a GL buffer's storage is and which elements changed since it was last
+/// uploaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BufferState {
+    usage: BufferUsage,
+    /// Elements the GPU storage has room for
+    capacity: usize,
+    dirty: Option<Range<usize>>,
+}
+
+impl BufferState {
+    fn new(usage: BufferUsage) -> Self {
+        BufferState {
+            usage,
+            capacity: 0,
+            dirty: None,
+        }
+    }
+
+    fn mark(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range,
+        });
+    }
+
+    /// How to upload the buffer now that it's `len` elements long. Grows the storage to the next
+    /// power of two, so appending one element at a time doesn't reallocate every time.
+    fn plan(&mut self, len: usize) -> Upload {
+        let dirty = match self.dirty.take() {
+            Some(dirty) => dirty,
+            None => return Upload::Nothing,
+        };
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two().max(MIN_CAPACITY);
+            return Upload::Everything {
+                capacity: self.capacity,
+            };
+        }
+        let dirty = dirty.start..dirty.end.min(len);
+        if dirty.start >= dirty.end {
+            Upload::Nothing
+        } else if self.usage == BufferUsage::Stream {
+            Upload::Everything {
+                capacity: self.capacity,
+            }
+        } else {
+            Upload::Range(dirty)
+        }
+    }
+}
+
+/// A `Vec` mirrored in a GL buffer, only sending what changed
+struct GpuVec<T> {
+    buffer: GlBuffer,
+    target: GLenum,
     data: Vec<T>,
-    indices: Vec<u32>, // NOTE this u32 must be the same size as GL_UNSIGNED_INT
-    attributes: Vec<VertexAttrib>,
-    stride: gl::types::GLsizei,
-}
-
-impl<T> VertexData<T> {
-    /// The type T should be a single struct or a tuple of types that each vertex should have
-    /// attached to it.
-    ///
-    /// # Arguments
-    ///
-    /// * `attributes` - A Vec that holds metadata about the type T used, that is later
-    /// procedurally passed to gl. Preferably use the `vertex_attribs::*` constants.
-    pub fn new(attributes: Vec<VertexAttrib>) -> Self {
-        let mut vao: gl::types::GLuint = 0;
-        let mut vbo: gl::types::GLuint = 0;
-        let mut ebo: gl::types::GLuint = 0;
+    state: BufferState,
+}
+
+impl<T: Copy> GpuVec<T> {
+    fn new(target: GLenum, usage: BufferUsage) -> Self {
+        GpuVec {
+            buffer: GlBuffer::new(),
+            target,
+            data: Vec::new(),
+            state: BufferState::new(usage),
+        }
+    }
+
+    fn set(&mut self, index: usize, value: T) {
+        self.data[index] = value;
+        self.state.mark(index..index + 1);
+    }
+
+    fn extend(&mut self, values: impl IntoIterator<Item = T>) {
+        let start = self.data.len();
+        self.data.extend(values);
+        self.state.mark(start..self.data.len());
+    }
+
+    fn replace(&mut self, values: &[T]) {
+        self.data.clear();
+        self.extend(values.iter().copied());
+    }
+
+    /// Needs the buffer's vertex array bound if it's an element buffer
+    fn upload(&mut self) {
+        let size = std::mem::size_of::<T>();
         unsafe {
-            gl::GenVertexArrays(1, &mut vao);
-            gl::GenBuffers(1, &mut vbo);
-            gl::GenBuffers(1, &mut ebo);
-        }
-        // Automatically calculate stride based on the total size of all the vertex attributes. Not
-        // sure if this is correct or not
-        let mut stride: gl::types::GLint = 0;
-        for a in attributes.iter() {
-            stride += a.size;
-            if !(a.components >= 1 && a.components <= 4) {
-                panic!(
-                    "Components of vector attribute must be between 1 and 4! You gave me: {}",
-                    a.components
-                );
+            match self.state.plan(self.data.len()) {
+                Upload::Nothing => {}
+                Upload::Everything { capacity } => {
+                    gl::BindBuffer(self.target, self.buffer.id());
+                    // new storage, even when it's the same size, is what orphans the old one
+                    gl::BufferData(
+                        self.target,
+                        (capacity * size) as gl::types::GLsizeiptr,
+                        std::ptr::null(),
+                        self.state.usage.to_gl(),
+                    );
+                    gl::BufferSubData(
+                        self.target,
+                        0,
+                        (self.data.len() * size) as gl::types::GLsizeiptr,
+                        self.data.as_ptr() as *const gl::types::GLvoid,
+                    );
+                }
+                Upload::Range(range) => {
+                    gl::BindBuffer(self.target, self.buffer.id());
+                    gl::BufferSubData(
+                        self.target,
+                        (range.start * size) as gl::types::GLintptr,
+                        (range.len() * size) as gl::types::GLsizeiptr,
+                        self.data[range].as_ptr() as *const gl::types::GLvoid,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Points `attributes` at the buffer bound to `GL_ARRAY_BUFFER`, in the bound vertex array.
+/// `divisor` is 0 for per-vertex attributes and 1 for per-instance ones.
+unsafe fn set_attributes(attributes: &[VertexAttrib], stride: usize, divisor: GLuint) {
+    for a in attributes.iter() {
+        let offset = a.offset as *const std::ffi::c_void;
+        let stride = stride as gl::types::GLsizei;
+        match a.kind {
+            AttribKind::Integer => {
+                gl::VertexAttribIPointer(a.location, a.components, a.gl_type, stride, offset)
             }
+            kind => gl::VertexAttribPointer(
+                a.location,
+                a.components,
+                a.gl_type,
+                (kind == AttribKind::Normalized) as gl::types::GLboolean,
+                stride,
+                offset,
+            ),
+        }
+        gl::EnableVertexAttribArray(a.location);
+        if divisor != 0 {
+            gl::VertexAttribDivisor(a.location, divisor);
         }
-        VertexData {
+    }
+}
+
+/// Vertices, and optionally indices into them and per-instance attributes `I`, kept on the CPU
+/// and the GPU. Changes are only sent to the GPU right before drawing, and only the parts that
+/// changed.
+pub struct VertexData<T, I = ()> {
+    /// `None` where there are no vertex arrays, the attributes are set up on every draw instead
+    vao: Option<GlVertexArray>,
+    vertices: GpuVec<T>,
+    indices: GpuVec<u32>,
+    instances: GpuVec<I>,
+    primitive: Primitive,
+}
+
+impl<T: Vertex, I: Vertex> VertexData<T, I> {
+    /// Needs a current GL context. Panics if `T`'s or `I`'s layout is wrong, see
+    /// `check_instance_layout`, or if `I` has attributes and there's no instancing.
+    pub fn new(usage: BufferUsage, capabilities: &Capabilities) -> Self {
+        if let Err(e) = check_instance_layout::<T, I>() {
+            panic!("{} isn't a valid vertex: {}", std::any::type_name::<T>(), e);
+        }
+        if !I::attributes().is_empty() && !capabilities.instancing {
+            panic!("{} has no instancing", capabilities.backend);
+        }
+        let vao = if capabilities.vertex_arrays {
+            Some(GlVertexArray::new())
+        } else {
+            None
+        };
+        let data = VertexData {
             vao,
-            vbo,
-            ebo,
-            data: Vec::new(),
-            indices: Vec::new(),
-            attributes,
-            stride,
+            vertices: GpuVec::new(gl::ARRAY_BUFFER, usage),
+            indices: GpuVec::new(gl::ELEMENT_ARRAY_BUFFER, usage),
+            instances: GpuVec::new(gl::ARRAY_BUFFER, usage),
+            primitive: Primitive::Triangles,
+        };
+        // the vertex array remembers which buffers the attributes come from, which stays the same
+        // however often their storage is replaced
+        if let Some(vao) = &data.vao {
+            unsafe {
+                gl::BindVertexArray(vao.id());
+                data.bind_attributes();
+                gl::BindVertexArray(0);
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+            }
         }
+        data
     }
-    fn activate(&self) {
-        unsafe {
-            gl::BindVertexArray(self.vao);
+
+    /// Points the attributes at the buffers, and binds the element buffer
+    unsafe fn bind_attributes(&self) {
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vertices.buffer.id());
+        set_attributes(&T::attributes(), std::mem::size_of::<T>(), 0);
+        if !I::attributes().is_empty() {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.instances.buffer.id());
+            set_attributes(&I::attributes(), std::mem::size_of::<I>(), 1);
         }
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.indices.buffer.id());
     }
-    fn deactivate(&self) {
-        unsafe {
-            gl::BindVertexArray(0);
+
+    /// Binds the vertex array, or without one sets the attributes up all over again
+    unsafe fn bind(&self) {
+        match &self.vao {
+            Some(vao) => gl::BindVertexArray(vao.id()),
+            None => self.bind_attributes(),
         }
     }
-    pub fn draw(&self) {
-        self.activate();
-        unsafe {
-            gl::DrawElements(
-                gl::TRIANGLES, // mode
-                self.indices.len() as i32,
-                gl::UNSIGNED_INT,
-                std::ptr::null(),
-            );
+
+    unsafe fn unbind(&self) {
+        match &self.vao {
+            Some(_) => gl::BindVertexArray(0),
+            None => {
+                // so the next thing drawn doesn't read attributes it doesn't have
+                for a in T::attributes().iter().chain(I::attributes().iter()) {
+                    gl::DisableVertexAttribArray(a.location);
+                }
+                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+            }
         }
-        self.deactivate();
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
     }
-    pub fn data(&mut self) -> &mut Vec<T> {
-        &mut self.data
+
+    /// Triangles unless set otherwise
+    pub fn set_primitive(&mut self, primitive: Primitive) {
+        self.primitive = primitive;
     }
+
+    pub fn data(&self) -> &[T] {
+        &self.vertices.data
+    }
+
     /// Length of vertices and attributes
     pub fn data_len(&self) -> usize {
-        self.data.len()
+        self.vertices.data.len()
     }
+
     pub fn get_vertex(&self, index: usize) -> &T {
-        &self.data[index]
+        &self.vertices.data[index]
     }
 
-    pub fn set_vertex_data(&mut self, index: usize, data: T, last_update: bool) {
-        self.data[index] = data;
-        self.update_on_gpu(last_update);
+    pub fn indices(&self) -> &[u32] {
+        &self.indices.data
     }
 
-    pub fn update_on_gpu(&mut self, last_update: bool) {
-        let storage_type = if last_update {
-            gl::STATIC_DRAW
-        } else {
-            gl::DYNAMIC_DRAW
-        };
-        fn vec_size<T>(v: &Vec<T>) -> gl::types::GLsizeiptr {
-            (v.len() * std::mem::size_of::<T>()) as gl::types::GLsizeiptr
+    pub fn instances(&self) -> &[I] {
+        &self.instances.data
+    }
+
+    /// Only this vertex is sent to the GPU again
+    pub fn set_vertex_data(&mut self, index: usize, data: T) {
+        self.vertices.set(index, data);
+    }
+
+    /// Adds vertices, and indices into them that start from 0 at the first new vertex
+    pub fn append(&mut self, new_data: &[T], new_indices: &[u32]) {
+        let offset = self.vertices.data.len() as u32;
+        self.vertices.extend(new_data.iter().copied());
+        self.indices.extend(new_indices.iter().map(|i| i + offset));
+    }
+
+    /// Replaces all the vertices and indices. Unlike `append`, the indices are used as is.
+    /// Without any indices the vertices are drawn in order.
+    pub fn replace(&mut self, new_data: &[T], new_indices: &[u32]) {
+        self.vertices.replace(new_data);
+        self.indices.replace(new_indices);
+    }
+
+    pub fn set_instance(&mut self, index: usize, instance: I) {
+        self.instances.set(index, instance);
+    }
+
+    /// Replaces every instance, see `draw`
+    pub fn replace_instances(&mut self, instances: &[I]) {
+        self.instances.replace(instances);
+    }
+
+    /// Sends whatever changed to the GPU
+    pub fn upload(&mut self) {
+        unsafe {
+            self.bind();
         }
+        self.vertices.upload();
+        self.instances.upload();
+        self.indices.upload();
         unsafe {
-            gl::BindVertexArray(self.vao);
-
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo); // bind the vbo buffer to the array_buffer slot
-            gl::BufferData(
-                gl::ARRAY_BUFFER, // target
-                vec_size(&self.data),
-                self.data.as_ptr() as *const gl::types::GLvoid, // pointer to data
-                storage_type,
-            );
-
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
-            gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                vec_size(&self.indices),
-                self.indices.as_ptr() as *const gl::types::GLvoid,
-                storage_type,
-            );
-
-            let mut attrib_array_i = 0;
-            let mut cur_pointer_offset = 0;
-            for a in self.attributes.iter() {
-                gl::VertexAttribPointer(
-                    attrib_array_i,
-                    a.components,
-                    a.gl_type,
-                    gl::FALSE,
-                    self.stride,
-                    cur_pointer_offset as *const std::ffi::c_void,
-                );
-                gl::EnableVertexAttribArray(attrib_array_i);
-                attrib_array_i += 1;
-                cur_pointer_offset += a.size;
+            self.unbind();
+        }
+    }
+
+    /// Uploads anything that changed, then draws. If `I` has any attributes everything is drawn
+    /// once per instance, all in one call.
+    pub fn draw(&mut self) {
+        self.upload();
+        let mode = self.primitive.to_gl();
+        let instanced = !I::attributes().is_empty();
+        let instances = self.instances.data.len() as gl::types::GLsizei;
+        let indices = self.indices.data.len() as gl::types::GLsizei;
+        let vertices = self.vertices.data.len() as gl::types::GLsizei;
+        unsafe {
+            self.bind();
+            match (indices > 0, instanced) {
+                (true, false) => {
+                    gl::DrawElements(mode, indices, gl::UNSIGNED_INT, std::ptr::null())
+                }
+                (true, true) => gl::DrawElementsInstanced(
+                    mode,
+                    indices,
+                    gl::UNSIGNED_INT,
+                    std::ptr::null(),
+                    instances,
+                ),
+                (false, false) => gl::DrawArrays(mode, 0, vertices),
+                (false, true) => gl::DrawArraysInstanced(mode, 0, vertices, instances),
+            }
+            self.unbind();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Particle {
+        position: na::Point2<f64>,
+        color: [u8; 4],
+        id: u32,
+    }
+
+    impl_vertex!(Particle {
+        position: 2,
+        color: 0 normalized,
+        id: 1 integer,
+    });
+
+    #[test]
+    fn lays_out_fields() {
+        let attributes = Particle::attributes();
+        assert_eq!(
+            attributes[0],
+            VertexAttrib {
+                location: 2,
+                gl_type: gl::DOUBLE,
+                components: 2,
+                kind: AttribKind::Float,
+                offset: 0,
+                size: 16,
             }
+        );
+        assert_eq!(attributes[1].gl_type, gl::UNSIGNED_BYTE);
+        assert_eq!(attributes[1].kind, AttribKind::Normalized);
+        assert_eq!(attributes[1].offset, 16);
+        assert_eq!(attributes[2].offset, 20);
+        assert_eq!(attributes[2].kind, AttribKind::Integer);
+        assert_eq!(check_layout::<Particle>(), Ok(()));
+    }
+
+    #[test]
+    fn finds_bad_layouts() {
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct Padded {
+            a: u8,
+            b: f32,
+        }
+        impl_vertex!(Padded { a: 0, b: 1 });
+        assert_eq!(
+            check_layout::<Padded>(),
+            Err("there's 3 bytes of padding before location 1".to_string())
+        );
+
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct Unused {
+            a: f32,
+            b: f32,
+        }
+        impl_vertex!(Unused { a: 0 });
+        assert_eq!(
+            check_layout::<Unused>(),
+            Err("the attributes add up to 4 bytes, but each vertex is 8".to_string())
+        );
 
-            gl::BindVertexArray(0);
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct Twice {
+            a: f32,
+            b: f32,
         }
+        impl_vertex!(Twice { a: 0, b: 0 });
+        assert_eq!(
+            check_layout::<Twice>(),
+            Err("location 0 is used twice".to_string())
+        );
+
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct FloatInt {
+            a: f32,
+        }
+        impl_vertex!(FloatInt { a: 0 integer });
+        assert!(check_layout::<FloatInt>().unwrap_err().contains("Integer"));
     }
 
-    /// Automatically offsets the indices to the current length of the vertex array (so you can
-    /// specify each index relative such that it starts from 0). Will also update the data onto GPU
-    /// memory.
-    /// # Arguments
-    ///
-    /// * `new_data` - new vertex data, directly copied into opengl memory so better be contiguous!
-    /// * `new_indices` - new index order with which to use the vertices, used to avoid repetition
-    /// * `last_update` - If this is the last vertex update, will be stored in static instead of
-    /// dynamic memory for greater efficiency
-    pub fn append(&mut self, new_data: &mut Vec<T>, new_indices: &mut Vec<u32>, last_update: bool) {
-        for elem in new_indices.iter_mut() {
-            *elem += self.data.len() as u32;
+    #[test]
+    fn instances_use_their_own_locations() {
+        assert_eq!(check_instance_layout::<Particle, ()>(), Ok(()));
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        struct Offset {
+            offset: na::Vector2<f32>,
         }
-        self.data.append(new_data);
-        self.indices.append(new_indices);
+        impl_vertex!(Offset { offset: 0 });
+        assert_eq!(
+            check_instance_layout::<Particle, Offset>(),
+            Err("location 0 is used by both the vertex and the instance".to_string())
+        );
+    }
+
+    #[test]
+    fn uploads_only_what_changed() {
+        let mut state = BufferState::new(BufferUsage::Dynamic);
+        assert_eq!(state.plan(0), Upload::Nothing);
+
+        state.mark(0..10);
+        assert_eq!(state.plan(10), Upload::Everything { capacity: 64 });
+        assert_eq!(state.plan(10), Upload::Nothing);
+
+        // changes are merged into one range
+        state.mark(3..4);
+        state.mark(7..8);
+        assert_eq!(state.plan(10), Upload::Range(3..8));
+
+        // growing past the capacity doubles it
+        state.mark(10..65);
+        assert_eq!(state.plan(65), Upload::Everything { capacity: 128 });
+
+        // shrinking doesn't, and whatever's past the end doesn't need sending
+        state.mark(0..65);
+        assert_eq!(state.plan(20), Upload::Range(0..20));
+        state.mark(20..65);
+        assert_eq!(state.plan(20), Upload::Nothing);
+        assert_eq!(state.capacity, 128);
+    }
 
-        self.update_on_gpu(last_update);
+    #[test]
+    fn streaming_orphans_every_change() {
+        let mut state = BufferState::new(BufferUsage::Stream);
+        state.mark(0..100);
+        assert_eq!(state.plan(100), Upload::Everything { capacity: 128 });
+        state.mark(0..1);
+        assert_eq!(state.plan(100), Upload::Everything { capacity: 128 });
+        assert_eq!(state.plan(100), Upload::Nothing);
     }
 }
@@ -0,0 +1,184 @@
+use crate::util::*;
+
+use rapier2d_f64::dynamics::{RigidBodyHandle, RigidBodySet};
+use serde::{Deserialize, Serialize};
+
+/// How a planet's pull weakens with distance from its center
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Falloff {
+    /// Newtonian, `mass / distance^2`
+    InverseSquare,
+    /// `mass / distance`, reaches further than real gravity which is nicer to play with
+    Inverse,
+    /// Always `mass`, no matter how far away
+    Constant,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Planet {
+    pub body: RigidBodyHandle,
+    /// Really the gravitational parameter (G * mass), i.e the acceleration at distance 1
+    pub mass: f64,
+    pub falloff: Falloff,
+}
+
+/// Point gravity towards every planet, applied as a force on each dynamic body before stepping
+/// the physics pipeline (whose own gravity vector is left at zero)
+#[derive(Default, Serialize, Deserialize)]
+pub struct Gravity {
+    planets: Vec<Planet>,
+}
+
+impl Gravity {
+    /// Distances are clamped to this so bodies that end up at a planet's center don't get shot
+    /// off to infinity
+    const MIN_DISTANCE: f64 = 0.5;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_planet(&mut self, planet: Planet) {
+        self.planets.push(planet);
+    }
+
+    pub fn planets(&self) -> &[Planet] {
+        &self.planets
+    }
+
+    pub fn is_planet(&self, handle: RigidBodyHandle) -> bool {
+        self.planets.iter().any(|p| p.body == handle)
+    }
+
+    /// Acceleration due to gravity at `point`, ignoring the planet `exclude` so planets don't
+    /// attract themselves
+    pub fn acceleration_at(
+        &self,
+        bodies: &RigidBodySet,
+        point: &P2,
+        exclude: Option<RigidBodyHandle>,
+    ) -> V2 {
+        let mut acceleration = V2::zeros();
+        for planet in self.planets.iter() {
+            if Some(planet.body) == exclude {
+                continue;
+            }
+            let center = match bodies.get(planet.body) {
+                Some(b) => P2::from(b.position().translation.vector),
+                None => continue,
+            };
+            let to_center = center - point;
+            let distance = to_center.norm().max(Self::MIN_DISTANCE);
+            let strength = match planet.falloff {
+                Falloff::InverseSquare => planet.mass / (distance * distance),
+                Falloff::Inverse => planet.mass / distance,
+                Falloff::Constant => planet.mass,
+            };
+            acceleration += to_center / distance * strength;
+        }
+        acceleration
+    }
+
+    /// The direction gravity pulls at `point`, straight down the screen (+Y) if nothing is pulling
+    pub fn down_at(&self, bodies: &RigidBodySet, point: &P2) -> V2 {
+        self.acceleration_at(bodies, point, None)
+            .try_normalize(1.0e-9)
+            .unwrap_or_else(|| V2::new(0.0, 1.0))
+    }
+
+    /// Adds the force of gravity to every awake dynamic body
+    pub fn apply(&self, bodies: &mut RigidBodySet) {
+        let forces: Vec<(RigidBodyHandle, V2)> = bodies
+            .iter()
+            .filter(|(_, b)| b.is_dynamic() && !b.is_sleeping())
+            .map(|(handle, b)| {
+                let acceleration = self.acceleration_at(bodies, &b.world_com, Some(handle));
+                (handle, acceleration * b.mass())
+            })
+            .collect();
+        for (handle, force) in forces {
+            bodies.get_mut(handle).unwrap().apply_force(force, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rapier2d_f64::dynamics::RigidBodyBuilder;
+    use rapier2d_f64::na::Isometry2;
+
+    fn planet_at(bodies: &mut RigidBodySet, pos: V2, mass: f64, falloff: Falloff) -> Planet {
+        let body = bodies.insert(
+            RigidBodyBuilder::new_static()
+                .position(Isometry2::new(pos, 0.0))
+                .build(),
+        );
+        Planet {
+            body,
+            mass,
+            falloff,
+        }
+    }
+
+    #[test]
+    fn pulls_towards_planet() {
+        let mut bodies = RigidBodySet::new();
+        let mut gravity = Gravity::new();
+        gravity.add_planet(planet_at(
+            &mut bodies,
+            V2::new(10.0, 0.0),
+            100.0,
+            Falloff::InverseSquare,
+        ));
+
+        let a = gravity.acceleration_at(&bodies, &P2::new(0.0, 0.0), None);
+        assert!((a - V2::new(1.0, 0.0)).norm() < 1e-9);
+        let a = gravity.acceleration_at(&bodies, &P2::new(10.0, -20.0), None);
+        assert!((a - V2::new(0.0, 0.25)).norm() < 1e-9);
+        assert!(
+            (gravity.down_at(&bodies, &P2::new(10.0, -20.0)) - V2::new(0.0, 1.0)).norm() < 1e-9
+        );
+    }
+
+    #[test]
+    fn falloffs() {
+        let mut bodies = RigidBodySet::new();
+        let origin = P2::new(0.0, 0.0);
+        for (falloff, expected) in [
+            (Falloff::InverseSquare, 0.25),
+            (Falloff::Inverse, 0.5),
+            (Falloff::Constant, 1.0),
+        ]
+        .iter()
+        {
+            let mut gravity = Gravity::new();
+            gravity.add_planet(planet_at(&mut bodies, V2::new(0.0, 2.0), 1.0, *falloff));
+            let a = gravity.acceleration_at(&bodies, &origin, None);
+            assert!((a.y - expected).abs() < 1e-9, "{:?}: {}", falloff, a);
+        }
+    }
+
+    #[test]
+    fn planets_cancel_out_and_ignore_themselves() {
+        let mut bodies = RigidBodySet::new();
+        let mut gravity = Gravity::new();
+        let left = planet_at(&mut bodies, V2::new(-5.0, 0.0), 10.0, Falloff::Inverse);
+        let left_handle = left.body;
+        gravity.add_planet(left);
+        gravity.add_planet(planet_at(
+            &mut bodies,
+            V2::new(5.0, 0.0),
+            10.0,
+            Falloff::Inverse,
+        ));
+
+        assert!(gravity.acceleration_at(&bodies, &P2::origin(), None).norm() < 1e-9);
+        // nothing pulls, so "down" falls back to +Y
+        assert_eq!(gravity.down_at(&bodies, &P2::origin()), V2::new(0.0, 1.0));
+
+        let a = gravity.acceleration_at(&bodies, &P2::new(-5.0, 0.0), Some(left_handle));
+        assert!((a - V2::new(1.0, 0.0)).norm() < 1e-9);
+    }
+}
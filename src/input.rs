@@ -0,0 +1,446 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+/// Something the player can do, independent of which button does it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Quit,
+    ToggleWireframe,
+    QuickSave,
+    QuickLoad,
+    Screenshot,
+    ToggleCameraRotation,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Quit,
+        Action::ToggleWireframe,
+        Action::QuickSave,
+        Action::QuickLoad,
+        Action::Screenshot,
+        Action::ToggleCameraRotation,
+    ];
+
+    /// The name used for this action in bindings files
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::Jump => "jump",
+            Action::Quit => "quit",
+            Action::ToggleWireframe => "toggle_wireframe",
+            Action::QuickSave => "quick_save",
+            Action::QuickLoad => "quick_load",
+            Action::Screenshot => "screenshot",
+            Action::ToggleCameraRotation => "toggle_camera_rotation",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+/// A physical input. Names are the ones SDL uses, i.e `Keycode::name()`, `Button::string()` and
+/// `Axis::string()`, so they can be written in a bindings file without depending on SDL here.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(String),
+    MouseButton(String),
+    ControllerButton(String),
+    /// One direction of an analog stick or trigger
+    ControllerAxis {
+        axis: String,
+        positive: bool,
+    },
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(name) => write!(f, "key:{}", name),
+            Binding::MouseButton(name) => write!(f, "mouse:{}", name),
+            Binding::ControllerButton(name) => write!(f, "button:{}", name),
+            Binding::ControllerAxis { axis, positive } => {
+                write!(f, "axis:{}{}", if *positive { '+' } else { '-' }, axis)
+            }
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = String;
+
+    /// Parses the same format `Display` writes, e.g `key:Space`, `mouse:Left`, `button:a` or
+    /// `axis:-leftx`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, name) = match s.find(':') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => return Err(format!("Binding '{}' is missing a 'kind:' prefix", s)),
+        };
+        if name.is_empty() {
+            return Err(format!("Binding '{}' has no name after the ':'", s));
+        }
+        match kind {
+            "key" => Ok(Binding::Key(name.to_string())),
+            "mouse" => Ok(Binding::MouseButton(name.to_string())),
+            "button" => Ok(Binding::ControllerButton(name.to_string())),
+            "axis" => {
+                let positive = match name.chars().next() {
+                    Some('+') => true,
+                    Some('-') => false,
+                    _ => {
+                        return Err(format!(
+                            "Axis binding '{}' must start with + or - for its direction",
+                            s
+                        ))
+                    }
+                };
+                Ok(Binding::ControllerAxis {
+                    axis: name[1..].to_string(),
+                    positive,
+                })
+            }
+            _ => Err(format!(
+                "Unknown binding kind '{}' in '{}', expected key, mouse, button or axis",
+                kind, s
+            )),
+        }
+    }
+}
+
+/// Something that happened to a physical input, translated out of whatever windowing library
+/// produced it (or made up by a test)
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Pressed(Binding),
+    Released(Binding),
+    /// An analog axis moved, `value` is from -1.0 to 1.0
+    AxisMoved {
+        axis: String,
+        value: f64,
+    },
+}
+
+/// Which bindings trigger each action
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bindings {
+    map: HashMap<Action, Vec<Binding>>,
+}
+
+impl Bindings {
+    /// No bindings at all, see `Bindings::default()` for the usual ones
+    pub fn new() -> Self {
+        Bindings {
+            map: HashMap::new(),
+        }
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.map.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind_all(&mut self, action: Action) {
+        self.map.remove(&action);
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.map.get(&action).map_or(&[], |b| b.as_slice())
+    }
+
+    /// Reads bindings out of a toml file with a list of bindings for every action, e.g
+    /// `jump = ["key:W", "button:a"]`. Actions that are left out keep the bindings they have in
+    /// `self`. If anything in the file is wrong, none of it is used.
+    pub fn load_overrides(&mut self, toml_source: &str) -> Result<(), String> {
+        let table: HashMap<String, Vec<String>> =
+            toml::from_str(toml_source).map_err(|e| format!("Invalid bindings file: {}", e))?;
+        let mut overrides = Bindings::new();
+        for (action_name, bindings) in table.iter() {
+            let action = Action::from_name(action_name)
+                .ok_or_else(|| format!("Unknown action '{}' in bindings file", action_name))?;
+            // an empty list still unbinds the action
+            overrides.map.entry(action).or_default();
+            for binding in bindings.iter() {
+                let binding = binding
+                    .parse()
+                    .map_err(|e| format!("In the bindings for '{}': {}", action_name, e))?;
+                overrides.bind(action, binding);
+            }
+        }
+        self.map.extend(overrides.map);
+        Ok(())
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings = Bindings::new();
+        let defaults: &[(Action, &[&str])] = &[
+            (
+                Action::MoveLeft,
+                &["key:A", "key:Left", "button:dpleft", "axis:-leftx"],
+            ),
+            (
+                Action::MoveRight,
+                &["key:D", "key:Right", "button:dpright", "axis:+leftx"],
+            ),
+            (Action::Jump, &["key:W", "key:Space", "key:Up", "button:a"]),
+            (Action::Quit, &["key:Escape", "button:start"]),
+            (Action::ToggleWireframe, &["key:Z"]),
+            (Action::QuickSave, &["key:F5"]),
+            (Action::QuickLoad, &["key:F9"]),
+            (Action::Screenshot, &["key:F12"]),
+            (Action::ToggleCameraRotation, &["key:R"]),
+        ];
+        for (action, names) in defaults.iter() {
+            for name in names.iter() {
+                bindings.bind(*action, name.parse().unwrap());
+            }
+        }
+        bindings
+    }
+}
+
+/// Current and previous frame's state of every action, so gameplay code can ask about actions
+/// instead of keys
+pub struct InputState {
+    bindings: Bindings,
+    /// How far down each held binding is, from 0.0 to 1.0
+    held: HashMap<Binding, f64>,
+    pressed_last_frame: HashSet<Action>,
+}
+
+impl InputState {
+    /// Analog inputs closer to rest than this are ignored
+    const DEADZONE: f64 = 0.2;
+    /// How far an analog input must go before it counts as pressed
+    const PRESS_THRESHOLD: f64 = 0.5;
+
+    pub fn new(bindings: Bindings) -> Self {
+        InputState {
+            bindings,
+            held: HashMap::new(),
+            pressed_last_frame: HashSet::new(),
+        }
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+    }
+
+    pub fn handle_event(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Pressed(binding) => {
+                self.held.insert(binding, 1.0);
+            }
+            InputEvent::Released(binding) => {
+                self.held.remove(&binding);
+            }
+            InputEvent::AxisMoved { axis, value } => {
+                let magnitude = if value.abs() < Self::DEADZONE {
+                    0.0
+                } else {
+                    value.abs().min(1.0)
+                };
+                for &positive in [true, false].iter() {
+                    let binding = Binding::ControllerAxis {
+                        axis: axis.clone(),
+                        positive,
+                    };
+                    if magnitude > 0.0 && (value > 0.0) == positive {
+                        self.held.insert(binding, magnitude);
+                    } else {
+                        self.held.remove(&binding);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Call once all of a frame's events have been handled and the frame's gameplay has run, so
+    /// `just_pressed` and `just_released` are relative to this frame next time
+    pub fn end_frame(&mut self) {
+        self.pressed_last_frame = Action::ALL
+            .iter()
+            .copied()
+            .filter(|a| self.pressed(*a))
+            .collect();
+    }
+
+    /// How strongly an action is held, from 0.0 to 1.0. Buttons are always fully held.
+    pub fn value(&self, action: Action) -> f64 {
+        self.bindings
+            .get(action)
+            .iter()
+            .filter_map(|b| self.held.get(b))
+            .fold(0.0, |a: f64, b| a.max(*b))
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.value(action) >= Self::PRESS_THRESHOLD
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && !self.pressed_last_frame.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        !self.pressed(action) && self.pressed_last_frame.contains(&action)
+    }
+
+    /// From -1.0 when only `negative` is held to 1.0 when only `positive` is
+    pub fn axis(&self, negative: Action, positive: Action) -> f64 {
+        self.value(positive) - self.value(negative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> Binding {
+        Binding::Key(name.to_string())
+    }
+
+    #[test]
+    fn binding_strings_round_trip() {
+        for s in [
+            "key:Space",
+            "mouse:Left",
+            "button:a",
+            "axis:-leftx",
+            "axis:+triggerright",
+        ]
+        .iter()
+        {
+            let binding: Binding = s.parse().unwrap();
+            assert_eq!(binding.to_string(), *s);
+        }
+        assert!("Space".parse::<Binding>().is_err());
+        assert!("key:".parse::<Binding>().is_err());
+        assert!("axis:leftx".parse::<Binding>().is_err());
+        assert!("joystick:0".parse::<Binding>().is_err());
+    }
+
+    #[test]
+    fn just_pressed_and_released() {
+        let mut input = InputState::new(Bindings::default());
+        assert!(!input.pressed(Action::Jump));
+
+        input.handle_event(InputEvent::Pressed(key("W")));
+        assert!(input.pressed(Action::Jump));
+        assert!(input.just_pressed(Action::Jump));
+        input.end_frame();
+        assert!(input.pressed(Action::Jump));
+        assert!(!input.just_pressed(Action::Jump));
+
+        // another binding for the same action doesn't count as a new press
+        input.handle_event(InputEvent::Pressed(key("Space")));
+        input.handle_event(InputEvent::Released(key("W")));
+        assert!(!input.just_pressed(Action::Jump));
+        assert!(!input.just_released(Action::Jump));
+        input.end_frame();
+
+        input.handle_event(InputEvent::Released(key("Space")));
+        assert!(input.just_released(Action::Jump));
+        input.end_frame();
+        assert!(!input.just_released(Action::Jump));
+    }
+
+    #[test]
+    fn axes() {
+        let mut input = InputState::new(Bindings::default());
+        input.handle_event(InputEvent::Pressed(key("D")));
+        assert_eq!(input.axis(Action::MoveLeft, Action::MoveRight), 1.0);
+        input.handle_event(InputEvent::Pressed(key("A")));
+        assert_eq!(input.axis(Action::MoveLeft, Action::MoveRight), 0.0);
+        input.handle_event(InputEvent::Released(key("D")));
+        input.handle_event(InputEvent::Released(key("A")));
+
+        let stick = |value| InputEvent::AxisMoved {
+            axis: "leftx".to_string(),
+            value,
+        };
+        input.handle_event(stick(-0.3));
+        assert_eq!(input.axis(Action::MoveLeft, Action::MoveRight), -0.3);
+        assert!(!input.pressed(Action::MoveLeft));
+        input.handle_event(stick(0.9));
+        assert_eq!(input.axis(Action::MoveLeft, Action::MoveRight), 0.9);
+        assert!(input.pressed(Action::MoveRight));
+        assert!(!input.pressed(Action::MoveLeft));
+        // inside the deadzone
+        input.handle_event(stick(0.1));
+        assert_eq!(input.axis(Action::MoveLeft, Action::MoveRight), 0.0);
+    }
+
+    #[test]
+    fn rebinding_from_file() {
+        let mut bindings = Bindings::default();
+        bindings
+            .load_overrides(
+                r#"
+                jump = ["key:K", "mouse:Left"]
+                move_left = []
+                "#,
+            )
+            .unwrap();
+        assert_eq!(
+            bindings.get(Action::Jump),
+            &[key("K"), Binding::MouseButton("Left".to_string())]
+        );
+        assert!(bindings.get(Action::MoveLeft).is_empty());
+        assert_eq!(
+            bindings.get(Action::MoveRight),
+            Bindings::default().get(Action::MoveRight)
+        );
+
+        let mut input = InputState::new(bindings);
+        input.handle_event(InputEvent::Pressed(key("W")));
+        assert!(!input.pressed(Action::Jump));
+        input.handle_event(InputEvent::Pressed(Binding::MouseButton(
+            "Left".to_string(),
+        )));
+        assert!(input.pressed(Action::Jump));
+    }
+
+    #[test]
+    fn bad_bindings_files() {
+        let err = Bindings::default()
+            .load_overrides(r#"fly = ["key:F"]"#)
+            .unwrap_err();
+        assert!(err.contains("fly"), "{}", err);
+        let err = Bindings::default()
+            .load_overrides(r#"jump = ["F"]"#)
+            .unwrap_err();
+        assert!(err.contains("jump") && err.contains("'F'"), "{}", err);
+        assert!(Bindings::default().load_overrides("jump = ").is_err());
+    }
+
+    #[test]
+    fn bad_files_change_nothing() {
+        // whichever of these is read first, the other one's still wrong
+        for file in &[
+            "jump = [\"key:K\"]\nquit = [\"F\"]",
+            "jump = [\"key:K\"]\nfly = [\"key:F\"]",
+        ] {
+            let mut bindings = Bindings::default();
+            assert!(bindings.load_overrides(file).is_err());
+            assert_eq!(bindings, Bindings::default());
+        }
+    }
+}
@@ -0,0 +1,512 @@
+use crate::config::Config;
+use crate::gravity::Falloff;
+use crate::util::*;
+
+use rapier2d_f64::dynamics::RigidBodyBuilder;
+use rapier2d_f64::geometry::{ColliderBuilder, SharedShape};
+use rapier2d_f64::na::{DVector, Isometry2};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Everything placed in the world before the first step, loaded from a json file like
+/// `assets/levels/default.json`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    #[serde(default)]
+    pub name: String,
+    /// Where the player starts
+    pub spawn: [f64; 2],
+    #[serde(default)]
+    pub planets: Vec<PlanetDesc>,
+    #[serde(default)]
+    pub bodies: Vec<BodyDesc>,
+    #[serde(default)]
+    pub triggers: Vec<TriggerDesc>,
+    /// Changes to the game's config just for this level
+    #[serde(default)]
+    pub overrides: LevelOverrides,
+}
+
+/// Parts of the `physics`, `player` and `camera` sections of a `Config`, with the same names.
+/// Kept as loose json here and only checked against the real thing in `Config::for_level`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelOverrides {
+    pub physics: serde_json::Map<String, serde_json::Value>,
+    pub player: serde_json::Map<String, serde_json::Value>,
+    pub camera: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyKind {
+    #[default]
+    Static,
+    Dynamic,
+}
+
+/// What a body looks like when drawn, the colors themselves are up to the renderer. Kept in the
+/// body's `user_data`, see `World::visual`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visual {
+    Player,
+    Planet,
+    Terrain,
+    Debris,
+}
+
+impl Visual {
+    pub const ALL: &'static [Visual] = &[
+        Visual::Player,
+        Visual::Planet,
+        Visual::Terrain,
+        Visual::Debris,
+    ];
+
+    /// Zero is left for bodies without a visual, like triggers
+    pub fn to_user_data(self) -> u128 {
+        self as u128 + 1
+    }
+
+    pub fn from_user_data(data: u128) -> Option<Visual> {
+        Self::ALL.get((data as usize).checked_sub(1)?).copied()
+    }
+}
+
+/// A static ball that pulls everything towards it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanetDesc {
+    pub position: [f64; 2],
+    pub radius: f64,
+    /// The acceleration at distance 1, see `Planet::mass`
+    pub mass: f64,
+    #[serde(default = "default_falloff")]
+    pub falloff: Falloff,
+    #[serde(default)]
+    pub material: Material,
+}
+
+fn default_falloff() -> Falloff {
+    Falloff::Inverse
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BodyDesc {
+    #[serde(default)]
+    pub kind: BodyKind,
+    pub position: [f64; 2],
+    /// In radians
+    #[serde(default)]
+    pub rotation: f64,
+    pub shape: ShapeDesc,
+    #[serde(default)]
+    pub material: Material,
+    /// Static bodies look like terrain and dynamic ones like debris unless this says otherwise
+    #[serde(default)]
+    pub visual: Option<Visual>,
+}
+
+/// An area that doesn't collide with anything, but notices the player being inside it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerDesc {
+    pub name: String,
+    pub position: [f64; 2],
+    #[serde(default)]
+    pub rotation: f64,
+    pub shape: ShapeDesc,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ShapeDesc {
+    Ball {
+        radius: f64,
+    },
+    Cuboid {
+        half_extents: [f64; 2],
+    },
+    /// The convex hull of `points`
+    Polygon {
+        points: Vec<[f64; 2]>,
+    },
+    /// Standing upright, `half_height` doesn't include the rounded ends
+    Capsule {
+        half_height: f64,
+        radius: f64,
+    },
+    /// Evenly spaced heights along the x axis, `scale` is the total width and the height multiplier
+    Heightfield {
+        heights: Vec<f64>,
+        scale: [f64; 2],
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Material {
+    pub restitution: f64,
+    pub friction: f64,
+    pub density: f64,
+    pub linear_damping: f64,
+    pub angular_damping: f64,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            restitution: 0.0,
+            friction: ColliderBuilder::default_friction(),
+            density: 1.0,
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+        }
+    }
+}
+
+pub(crate) fn positive(field: &str, value: f64) -> Result<(), String> {
+    if value > 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{} must be positive, got {}", field, value))
+    }
+}
+
+pub(crate) fn not_negative(field: &str, value: f64) -> Result<(), String> {
+    if value >= 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{} can't be negative, got {}", field, value))
+    }
+}
+
+fn isometry(position: [f64; 2], rotation: f64) -> Isometry2<f64> {
+    Isometry2::new(V2::new(position[0], position[1]), rotation)
+}
+
+impl ShapeDesc {
+    pub fn build(&self) -> Result<SharedShape, String> {
+        Ok(match self {
+            ShapeDesc::Ball { radius } => {
+                positive("radius", *radius)?;
+                SharedShape::ball(*radius)
+            }
+            ShapeDesc::Cuboid { half_extents } => {
+                positive("half_extents[0]", half_extents[0])?;
+                positive("half_extents[1]", half_extents[1])?;
+                SharedShape::cuboid(half_extents[0], half_extents[1])
+            }
+            ShapeDesc::Polygon { points } => {
+                let points: Vec<P2> = points.iter().map(|p| P2::new(p[0], p[1])).collect();
+                SharedShape::convex_hull(&points).ok_or_else(|| {
+                    "points must have at least 3 points that aren't all on a line".to_string()
+                })?
+            }
+            ShapeDesc::Capsule {
+                half_height,
+                radius,
+            } => {
+                not_negative("half_height", *half_height)?;
+                positive("radius", *radius)?;
+                SharedShape::capsule(
+                    P2::new(0.0, -half_height),
+                    P2::new(0.0, *half_height),
+                    *radius,
+                )
+            }
+            ShapeDesc::Heightfield { heights, scale } => {
+                if heights.len() < 2 {
+                    return Err(format!(
+                        "heights needs at least 2 values, got {}",
+                        heights.len()
+                    ));
+                }
+                positive("scale[0]", scale[0])?;
+                positive("scale[1]", scale[1])?;
+                SharedShape::heightfield(
+                    DVector::from_column_slice(heights),
+                    V2::new(scale[0], scale[1]),
+                )
+            }
+        })
+    }
+}
+
+impl Material {
+    fn validate(&self) -> Result<(), String> {
+        not_negative("restitution", self.restitution)?;
+        not_negative("friction", self.friction)?;
+        positive("density", self.density)?;
+        not_negative("linear_damping", self.linear_damping)?;
+        not_negative("angular_damping", self.angular_damping)
+    }
+
+    fn collider(&self, shape: SharedShape) -> ColliderBuilder {
+        ColliderBuilder::new(shape)
+            .restitution(self.restitution)
+            .friction(self.friction)
+            .density(self.density)
+    }
+
+    fn body(&self, body: RigidBodyBuilder) -> RigidBodyBuilder {
+        body.linear_damping(self.linear_damping)
+            .angular_damping(self.angular_damping)
+    }
+}
+
+/// Prefixes an error with which part of the level it's about, e.g `bodies[2]: shape: ...`
+fn context<T>(result: Result<T, String>, path: impl FnOnce() -> String) -> Result<T, String> {
+    result.map_err(|e| format!("{}: {}", path(), e))
+}
+
+impl PlanetDesc {
+    pub fn build(&self) -> Result<(RigidBodyBuilder, ColliderBuilder), String> {
+        positive("radius", self.radius)?;
+        context(self.material.validate(), || "material".to_string())?;
+        let body = RigidBodyBuilder::new_static()
+            .position(isometry(self.position, 0.0))
+            .user_data(Visual::Planet.to_user_data());
+        let collider = self.material.collider(SharedShape::ball(self.radius));
+        Ok((self.material.body(body), collider))
+    }
+}
+
+impl BodyDesc {
+    pub fn build(&self) -> Result<(RigidBodyBuilder, ColliderBuilder), String> {
+        let shape = context(self.shape.build(), || "shape".to_string())?;
+        context(self.material.validate(), || "material".to_string())?;
+        let (body, visual) = match self.kind {
+            BodyKind::Static => (RigidBodyBuilder::new_static(), Visual::Terrain),
+            BodyKind::Dynamic => (RigidBodyBuilder::new_dynamic(), Visual::Debris),
+        };
+        let body = body
+            .position(isometry(self.position, self.rotation))
+            .user_data(self.visual.unwrap_or(visual).to_user_data());
+        Ok((self.material.body(body), self.material.collider(shape)))
+    }
+}
+
+impl TriggerDesc {
+    pub fn build(&self) -> Result<(RigidBodyBuilder, ColliderBuilder), String> {
+        let shape = context(self.shape.build(), || "shape".to_string())?;
+        let body = RigidBodyBuilder::new_static().position(isometry(self.position, self.rotation));
+        Ok((body, ColliderBuilder::new(shape).sensor(true)))
+    }
+}
+
+impl Level {
+    /// Parses a level, errors say where in the file things went wrong
+    pub fn parse(json: &str) -> Result<Level, String> {
+        let level: Level = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        level.validate()?;
+        Ok(level)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Level, String> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read level {}: {}", path.display(), e))?;
+        Self::parse(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The level played when none is given
+    pub fn builtin() -> Level {
+        Self::parse(include_str!("../assets/levels/default.json"))
+            .expect("the built in level is invalid")
+    }
+
+    /// The built in level's planet with `bodies` balls and boxes in rings around it, for seeing
+    /// how many bodies the game can handle
+    pub fn benchmark(bodies: usize) -> Level {
+        const SPACING: f64 = 3.0;
+        let mut level = Level::builtin();
+        level.name = format!("Benchmark with {} bodies", bodies);
+        level.bodies.clear();
+        let planet = level.planets[0].clone();
+        let spawn = V2::new(level.spawn[0], level.spawn[1]);
+        let center = V2::new(planet.position[0], planet.position[1]);
+        let mut ring_radius = planet.radius + SPACING * 2.0;
+        let mut ball = true;
+        while level.bodies.len() < bodies {
+            let count = (std::f64::consts::TAU * ring_radius / SPACING) as usize;
+            for i in 0..count.min(bodies - level.bodies.len()) {
+                let angle = std::f64::consts::TAU * i as f64 / count as f64;
+                let position = center + V2::new(angle.cos(), angle.sin()) * ring_radius;
+                // leave the player room to spawn
+                if (position - spawn).norm() < SPACING * 2.0 {
+                    continue;
+                }
+                ball = !ball;
+                let shape = if ball {
+                    ShapeDesc::Ball { radius: 0.8 }
+                } else {
+                    ShapeDesc::Cuboid {
+                        half_extents: [0.7, 0.7],
+                    }
+                };
+                level.bodies.push(BodyDesc {
+                    kind: BodyKind::Dynamic,
+                    position: [position.x, position.y],
+                    rotation: angle,
+                    shape,
+                    material: Material::default(),
+                    visual: None,
+                });
+            }
+            ring_radius += SPACING;
+        }
+        level
+    }
+
+    /// Checks for anything that would only fail once the level is built, like negative sizes
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, planet) in self.planets.iter().enumerate() {
+            context(planet.build(), || format!("planets[{}]", i))?;
+        }
+        for (i, body) in self.bodies.iter().enumerate() {
+            context(body.build(), || format!("bodies[{}]", i))?;
+        }
+        for (i, trigger) in self.triggers.iter().enumerate() {
+            context(trigger.build(), || format!("triggers[{}]", i))?;
+        }
+        Config::default().for_level(self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_level_loads() {
+        let level = Level::builtin();
+        assert_eq!(level.planets.len(), 1);
+        assert_eq!(level.bodies.len(), 2);
+        assert_eq!(level.planets[0].falloff, Falloff::Inverse);
+        assert_eq!(level.bodies[0].kind, BodyKind::Static);
+    }
+
+    #[test]
+    fn benchmark_level() {
+        let level = Level::benchmark(10_000);
+        assert_eq!(level.bodies.len(), 10_000);
+        assert_eq!(level.validate(), Ok(()));
+        let spawn = V2::new(level.spawn[0], level.spawn[1]);
+        let closest = level
+            .bodies
+            .iter()
+            .map(|b| (V2::new(b.position[0], b.position[1]) - spawn).norm())
+            .fold(f64::MAX, f64::min);
+        assert!(closest > 2.0, "{}", closest);
+    }
+
+    #[test]
+    fn every_shape() {
+        let level = Level::parse(
+            r#"{
+                "spawn": [1, 2],
+                "bodies": [
+                    { "position": [0, 0], "shape": { "type": "ball", "radius": 1 } },
+                    { "position": [0, 0], "shape": { "type": "cuboid", "half_extents": [1, 2] } },
+                    {
+                        "kind": "dynamic",
+                        "position": [0, 0],
+                        "rotation": 0.5,
+                        "shape": { "type": "polygon", "points": [[0, 0], [1, 0], [0, 1]] },
+                        "material": { "friction": 0.1, "linear_damping": 2 }
+                    },
+                    { "position": [0, 0], "shape": { "type": "capsule", "half_height": 1, "radius": 0.5 } },
+                    { "position": [0, 0], "shape": { "type": "heightfield", "heights": [0, 1, 0], "scale": [10, 1] } }
+                ],
+                "triggers": [
+                    { "name": "goal", "position": [5, 5], "shape": { "type": "ball", "radius": 2 } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(level.spawn, [1.0, 2.0]);
+        assert_eq!(level.bodies.len(), 5);
+        assert_eq!(level.bodies[2].kind, BodyKind::Dynamic);
+        assert_eq!(level.bodies[2].material.friction, 0.1);
+        assert_eq!(level.bodies[2].material.restitution, 0.0);
+        assert_eq!(level.triggers[0].name, "goal");
+    }
+
+    #[test]
+    fn visuals() {
+        for visual in Visual::ALL {
+            assert_eq!(Visual::from_user_data(visual.to_user_data()), Some(*visual));
+        }
+        assert_eq!(Visual::from_user_data(0), None);
+        assert_eq!(Visual::from_user_data(1000), None);
+
+        let level = Level::parse(
+            r#"{
+                "spawn": [0, 0],
+                "bodies": [
+                    { "position": [0, 0], "shape": { "type": "ball", "radius": 1 } },
+                    { "kind": "dynamic", "position": [0, 0], "shape": { "type": "ball", "radius": 1 } },
+                    { "position": [0, 0], "shape": { "type": "ball", "radius": 1 }, "visual": "debris" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let visuals: Vec<_> = level
+            .bodies
+            .iter()
+            .map(|b| Visual::from_user_data(b.build().unwrap().0.build().user_data))
+            .collect();
+        assert_eq!(
+            visuals,
+            vec![
+                Some(Visual::Terrain),
+                Some(Visual::Debris),
+                Some(Visual::Debris)
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_have_context() {
+        let err =
+            Level::parse("{\n \"spawn\": [0, 0],\n \"bodies\": [{ \"position\": [0, 0] }]\n}")
+                .unwrap_err();
+        assert!(err.contains("missing field `shape`"), "{}", err);
+        assert!(err.contains("line 3"), "{}", err);
+
+        let err = Level::parse(
+            r#"{ "spawn": [0, 0], "bodies": [{ "position": [0, 0], "shape": { "type": "star" } }] }"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("unknown variant `star`"), "{}", err);
+
+        let err = Level::parse(r#"{ "spawn": [0, 0], "sapwn": [0, 0] }"#).unwrap_err();
+        assert!(err.contains("unknown field `sapwn`"), "{}", err);
+
+        let err = Level::parse(
+            r#"{
+                "spawn": [0, 0],
+                "bodies": [
+                    { "position": [0, 0], "shape": { "type": "ball", "radius": 1 } },
+                    { "position": [0, 0], "shape": { "type": "ball", "radius": -1 } }
+                ]
+            }"#,
+        )
+        .unwrap_err();
+        assert_eq!(err, "bodies[1]: shape: radius must be positive, got -1");
+
+        let err = Level::parse(
+            r#"{
+                "spawn": [0, 0],
+                "planets": [{ "position": [0, 0], "radius": 5, "mass": 1, "material": { "density": 0 } }]
+            }"#,
+        )
+        .unwrap_err();
+        assert_eq!(err, "planets[0]: material: density must be positive, got 0");
+    }
+}
@@ -0,0 +1,27 @@
+extern crate gl;
+extern crate nalgebra as na;
+
+pub mod camera;
+pub mod cli;
+pub mod config;
+pub mod gl_backend;
+pub mod gl_resources;
+pub mod gl_shaders;
+pub mod gl_uniforms;
+#[macro_use]
+pub mod gl_vertices;
+pub mod gravity;
+pub mod input;
+pub mod level;
+pub mod player_controller;
+pub mod quick_draw;
+pub mod render;
+pub mod replay;
+pub mod screenshot;
+pub mod shader_assets;
+pub mod snapshot;
+pub mod software_render;
+pub mod timestep;
+pub mod util;
+pub mod view;
+pub mod world;
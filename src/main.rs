@@ -2,33 +2,80 @@ extern crate gl;
 extern crate nalgebra as na;
 extern crate sdl2;
 
-#[macro_use]
-pub mod gl_shaders;
-pub mod gl_vertices;
-mod quick_draw;
-
-use quick_draw::*;
-
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::video::GLProfile;
-use std::time::Duration;
-
-use rapier2d_f64::dynamics::{RigidBody, RigidBodyHandle};
-use rapier2d_f64::geometry::{
-    BroadPhase, ColliderBuilder, ColliderSet, NarrowPhase, Ray, SharedShape,
-};
-use rapier2d_f64::na::Vector2;
-use rapier2d_f64::na::{ComplexField, Isometry2};
-use rapier2d_f64::pipeline::{PhysicsPipeline, QueryPipeline};
-use rapier2d_f64::{
-    dynamics::{CCDSolver, IntegrationParameters, JointSet, RigidBodyBuilder, RigidBodySet},
-    na::Translation2,
-};
-
-// TODO put these type aliases into util mod
-type P2 = na::Point2<f64>;
-type V2 = na::Vector2<f64>;
+use planets::camera::{Camera2D, CameraConfig};
+use planets::cli::{Args, USAGE};
+use planets::config::Config;
+use planets::gl_backend::{Capabilities, GlBackend};
+use planets::gl_resources;
+use planets::input::{Action, Binding, Bindings, InputEvent, InputState};
+use planets::level::Level;
+use planets::quick_draw::DrawingContext;
+use planets::render::{draw_world, Color, RenderBackend, BLACK, WHITE};
+use planets::replay::Recording;
+use planets::screenshot;
+use planets::software_render::SoftwareRenderer;
+use planets::timestep::{FixedTimestep, SystemClock};
+use planets::view::{ScaleMode, View};
+use planets::world::{Input, World};
+
+use sdl2::controller::GameController;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::video::{GLContext, GLProfile, SwapInterval, Window};
+use sdl2::VideoSubsystem;
+
+/// Settings in this file replace the defaults, if it exists and no other config is given
+const CONFIG_PATH: &str = "planets.toml";
+
+/// Bindings in this file replace the defaults, if it exists
+const BINDINGS_PATH: &str = "bindings.toml";
+
+/// Where quicksaves go, a `.json` path here would save them readable instead
+const QUICKSAVE_PATH: &str = "quicksave.bin";
+
+/// Shaders are loaded from here, and reloaded whenever they're saved
+const SHADER_DIR: &str = "assets/shaders";
+
+const ERROR_COLOR: Color = [0.8, 0.0, 0.0, 1.0];
+
+/// Translates the SDL events that `InputState` cares about
+fn to_input_event(event: &Event) -> Option<InputEvent> {
+    match event {
+        Event::KeyDown {
+            keycode: Some(k), ..
+        } => Some(InputEvent::Pressed(Binding::Key(k.name()))),
+        Event::KeyUp {
+            keycode: Some(k), ..
+        } => Some(InputEvent::Released(Binding::Key(k.name()))),
+        Event::MouseButtonDown { mouse_btn, .. } => Some(InputEvent::Pressed(
+            Binding::MouseButton(format!("{:?}", mouse_btn)),
+        )),
+        Event::MouseButtonUp { mouse_btn, .. } => Some(InputEvent::Released(Binding::MouseButton(
+            format!("{:?}", mouse_btn),
+        ))),
+        Event::ControllerButtonDown { button, .. } => Some(InputEvent::Pressed(
+            Binding::ControllerButton(button.string()),
+        )),
+        Event::ControllerButtonUp { button, .. } => Some(InputEvent::Released(
+            Binding::ControllerButton(button.string()),
+        )),
+        Event::ControllerAxisMotion { axis, value, .. } => Some(InputEvent::AxisMoved {
+            axis: axis.string(),
+            value: *value as f64 / i16::MAX as f64,
+        }),
+        _ => None,
+    }
+}
+
+fn load_bindings() -> Bindings {
+    let mut bindings = Bindings::default();
+    if let Ok(source) = std::fs::read_to_string(BINDINGS_PATH) {
+        if let Err(e) = bindings.load_overrides(&source) {
+            println!("Ignoring {}: {}", BINDINGS_PATH, e);
+            return Bindings::default();
+        }
+    }
+    bindings
+}
 
 // https://www.khronos.org/opengl/wiki/OpenGL_Error
 extern "system" fn message_callback(
@@ -58,230 +105,452 @@ extern "system" fn message_callback(
     }
 }
 
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// The config file with the command line's changes on top
+fn load_config(args: &Args) -> Result<Config, String> {
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None if std::path::Path::new(CONFIG_PATH).exists() => Config::load(CONFIG_PATH)?,
+        None => Config::default(),
+    };
+    args.apply(&mut config)?;
+    Ok(config)
+}
+
+/// What the game shows when it starts, looking at the level's spawn point
+fn initial_camera(level: &Level, config: &CameraConfig) -> Camera2D {
+    let spawn = na::Vector2::new(level.spawn[0] as f32, level.spawn[1] as f32);
+    Camera2D::with_config(spawn, config.clone())
+}
+
+/// Draws to the part of the window `view` says, with black bars around it if there's any room
+/// left
+fn use_view(view: &View, drawable: (u32, u32)) {
+    let [x, y, width, height] = view.viewport;
+    unsafe {
+        gl::Viewport(x, y, width, height);
+        if view.is_letterboxed(drawable) {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            // so clearing at the start of the frame leaves the bars alone
+            gl::Enable(gl::SCISSOR_TEST);
+            gl::Scissor(x, y, width, height);
+        } else {
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+    }
+}
+
+/// Shows `error` over the top of the screen, as much of it as fits
+fn draw_error(backend: &mut impl RenderBackend, error: &str, screen_size: na::Vector2<f32>) {
+    const TEXT_SIZE: f32 = 10.0;
+    const LINE_HEIGHT: f32 = 16.0;
+    let lines: Vec<&str> = error.lines().take(20).collect();
+    let bottom = LINE_HEIGHT * (lines.len() as f32 + 1.0);
+    backend.fill_rect(
+        na::Vector2::zeros(),
+        na::Vector2::new(screen_size.x, bottom),
+        [1.0, 1.0, 1.0, 0.9],
+    );
+    for (i, line) in lines.iter().enumerate() {
+        let top_left = na::Vector2::new(LINE_HEIGHT, LINE_HEIGHT * (i as f32 + 0.5));
+        let color = if i == 0 { ERROR_COLOR } else { BLACK };
+        backend.text(top_left, line, TEXT_SIZE, color);
+    }
+}
+
+/// Plays a recording back without opening a window, and prints a hash of where everything ended
+/// up so it can be compared between versions
+fn run_replay(path: &str) {
+    let recording = or_exit(Recording::load(path));
+    let world = or_exit(recording.replay());
+    println!(
+        "Replayed {} steps, final state hash {:016x}",
+        recording.inputs().len(),
+        world.state_hash()
+    );
+}
+
+/// Plays a recording back without opening a window, saving what each step looks like
+fn run_replay_frames(path: &str, dir: &str, frames: Option<usize>, config: &Config) {
+    let recording = or_exit(Recording::load(path));
+    let level = &recording.level;
+    // the world comes from the recording, only how it's looked at comes from the config
+    let config = or_exit(config.for_level(level));
+    let mut renderer =
+        SoftwareRenderer::new(config.window.width as usize, config.window.height as usize);
+    let mut camera = initial_camera(level, &config.camera);
+    let written = or_exit(screenshot::render_replay_frames(
+        &recording,
+        dir,
+        frames,
+        &mut renderer,
+        &mut camera,
+    ));
+    println!("Saved {} frames to {}", written, dir);
+}
+
+/// Saves what the level looks like before anything moves, without opening a window
+fn run_screenshot(level: &Level, path: &str, config: &Config) {
+    let world = or_exit(World::from_level(config.world_config(), level));
+    let size = (config.window.width, config.window.height);
+    let mut renderer = SoftwareRenderer::new(size.0 as usize, size.1 as usize);
+    let view = View::new(size, size, ScaleMode::Expand);
+    let camera = initial_camera(level, &config.camera);
+    renderer.begin_frame(&view.projection(), &camera.matrix(view.screen_size), WHITE);
+    draw_world(&world, &mut renderer, 1.0);
+    renderer.end_frame();
+    or_exit(screenshot::save_png(
+        path,
+        renderer.width(),
+        renderer.height(),
+        renderer.pixels(),
+    ));
+}
+
+/// Runs the level for `steps` physics steps with nobody touching anything, and prints a hash of
+/// where everything ended up
+fn run_headless(level: &Level, args: &Args, config: &Config, steps: u64) {
+    let mut world = or_exit(World::from_level(config.world_config(), level));
+    let mut recording = Recording::new(config, level.clone());
+    for _ in 0..steps {
+        recording.record(&Input::default());
+        world.step(&Input::default());
+    }
+    println!(
+        "Ran {} steps, final state hash {:016x}",
+        steps,
+        world.state_hash()
+    );
+    if let Some(path) = &args.record {
+        or_exit(recording.save(path));
+        println!("Saved recording to {}", path);
+    }
+}
+
+/// Makes a context for the first of `backends` the driver has and the game can draw with
+fn create_gl_context(
+    video: &VideoSubsystem,
+    window: &Window,
+    backends: &[GlBackend],
+) -> Result<(GLContext, Capabilities), String> {
+    let mut failures = Vec::new();
+    for &backend in backends {
+        let gl_attr = video.gl_attr();
+        let (major, minor) = backend.version();
+        gl_attr.set_context_version(major, minor);
+        if backend.is_gles() {
+            gl_attr.set_context_profile(GLProfile::GLES);
+            gl_attr.set_context_flags().set();
+        } else {
+            gl_attr.set_context_profile(GLProfile::Core);
+            // macOS only makes core contexts that are forward compatible
+            gl_attr.set_context_flags().forward_compatible().set();
+        }
+        let context = match window.gl_create_context() {
+            Ok(context) => context,
+            Err(e) => {
+                failures.push(format!("{}: {}", backend, e));
+                continue;
+            }
+        };
+        gl::load_with(|name| video.gl_get_proc_address(name) as *const _);
+        let capabilities = Capabilities::detect(backend);
+        match capabilities.missing() {
+            None => return Ok((context, capabilities)),
+            Some(missing) => failures.push(format!("{} can't be used", missing)),
+        }
+    }
+    Err(format!(
+        "Failed to create a GL context: {}",
+        failures.join(", ")
+    ))
+}
+
 fn main() {
+    let args = or_exit(Args::parse(std::env::args().skip(1)));
+    if args.help {
+        print!("{}", USAGE);
+        return;
+    }
+    let config = or_exit(load_config(&args));
+    if let Some(replay) = &args.replay {
+        match &args.frames {
+            Some(dir) => run_replay_frames(replay, dir, args.frame_count, &config),
+            None => run_replay(replay),
+        }
+        return;
+    }
+    let level = or_exit(match (&args.level, args.benchmark) {
+        (Some(path), _) => Level::load(path),
+        (None, Some(bodies)) => Ok(Level::benchmark(bodies as usize)),
+        (None, None) => Ok(Level::builtin()),
+    });
+    let config = or_exit(config.for_level(&level));
+    if let Some(path) = &args.screenshot {
+        run_screenshot(&level, path, &config);
+        return;
+    }
+    if let Some(steps) = args.headless {
+        run_headless(&level, &args, &config, steps);
+        return;
+    }
+    let scale_mode = if config.window.letterbox {
+        ScaleMode::Letterbox {
+            width: config.window.width as f32,
+            height: config.window.height as f32,
+        }
+    } else {
+        ScaleMode::Expand
+    };
+
     // initialize sdl2 and opengl
     let sdl_context;
     let mut event_pump;
-    let window;
+    let controller_subsystem;
+    let mut window;
     let _ctx; // when this is dropped the opengl context is destroyed
+    let capabilities;
     {
         sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
         event_pump = sdl_context.event_pump().unwrap();
+        controller_subsystem = sdl_context.game_controller().unwrap();
 
-        let gl_attr = video_subsystem.gl_attr();
-        gl_attr.set_context_profile(sdl2::video::GLProfile::GLES);
-        gl_attr.set_context_major_version(2);
-        gl_attr.set_context_minor_version(0);
-
-        window = video_subsystem
-            .window("explain", 1000, 900)
+        let mut builder = video_subsystem.window(
+            &config.window.title,
+            config.window.width,
+            config.window.height,
+        );
+        builder
             .opengl()
             .position_centered()
             .resizable()
-            .build()
-            .unwrap();
+            .allow_highdpi();
+        if config.window.fullscreen {
+            builder.fullscreen_desktop();
+        }
+        window = builder.build().unwrap();
 
-        _ctx = window.gl_create_context().unwrap();
-        gl::load_with(|name| video_subsystem.gl_get_proc_address(name) as *const _);
+        let backends = match &config.window.backend {
+            Some(backend) => std::slice::from_ref(backend),
+            None => GlBackend::ALL,
+        };
+        let (context, found) = or_exit(create_gl_context(&video_subsystem, &window, backends));
+        _ctx = context;
+        capabilities = found;
+        println!("Drawing with {}", capabilities.backend);
 
-        debug_assert_eq!(gl_attr.context_profile(), GLProfile::GLES);
-        debug_assert_eq!(gl_attr.context_version(), (2, 0));
+        // not every driver supports vsync, the loop sleeps between steps anyways
+        let _ = video_subsystem.gl_set_swap_interval(if config.window.vsync {
+            SwapInterval::VSync
+        } else {
+            SwapInterval::Immediate
+        });
 
         unsafe {
-            gl::Viewport(0, 0, 1000, 900);
-            gl::Enable(gl::DEBUG_OUTPUT);
-            gl::DebugMessageCallback(Some(message_callback), std::ptr::null());
+            // only some drivers have it, GLES 2.0 never does
+            if capabilities.debug_output {
+                gl::Enable(gl::DEBUG_OUTPUT);
+                gl::DebugMessageCallback(Some(message_callback), std::ptr::null());
+            }
             gl::Enable(gl::BLEND);
             gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
         }
     }
 
-    // initialize the physics
-    let mut pipeline = PhysicsPipeline::new();
-    let mut query = QueryPipeline::new();
-    let gravity = V2::new(0.0, 50.0);
-    let integration_parameters = IntegrationParameters::default();
-    let mut broad_phase = BroadPhase::new();
-    let mut narrow_phase = NarrowPhase::new();
-    let mut bodies = RigidBodySet::new();
-    let mut colliders = ColliderSet::new();
-    let mut joints = JointSet::new();
-    let mut ccd_solver = CCDSolver::new();
-    // We ignore physics hooks and contact events for now.
-    let physics_hooks = ();
-    let event_handler = ();
-
-    let circle = RigidBodyBuilder::new_dynamic()
-        .position(Isometry2::new(V2::new(0.0, 0.0), 0.0))
-        .linear_damping(0.5)
-        .build();
-    let circle_collider = ColliderBuilder::new(SharedShape::ball(1.0))
-        .restitution(0.0)
-        .build();
-    let circle_ref = bodies.insert(circle);
-    let circle_collider_handle = colliders.insert(circle_collider, circle_ref, &mut bodies);
-
-    fn new_static_box(pos: V2, hx: f64, hy: f64, colliders: &mut ColliderSet, bodies: &mut RigidBodySet) {
-        let floor = RigidBodyBuilder::new_static()
-            .position(Isometry2::new(pos, 0.0f64.to_radians()))
-            .build();
-        let floor_collider = ColliderBuilder::new(SharedShape::cuboid(hx, hy))
-            .restitution(0.2)
-            .build();
-        let floor_ref = bodies.insert(floor);
-        let floor_collider_handle = colliders.insert(floor_collider, floor_ref, bodies);
-    }
-    new_static_box(V2::new(0.0, 100.0), 800.0, 10.0, &mut colliders, &mut bodies);
-    new_static_box(V2::new(-50.0, 100.0), 10.0, 100.0, &mut colliders, &mut bodies);
-    new_static_box(V2::new(75.0, 100.0), 10.0, 100.0, &mut colliders, &mut bodies);
+    let mut world = or_exit(World::from_level(config.world_config(), &level));
+    let mut recording = args
+        .record
+        .as_ref()
+        .map(|_| Recording::new(&config, level.clone()));
 
-    let mut projection = nalgebra::Orthographic3::new(0.0, 1000.0, 900.0, 0.0, -1.0, 1.0);
-    let mut camera = nalgebra::Matrix4::new_translation(&na::Vector3::new(400.0, 0.0, 0.0));
-    camera *= na::Matrix4::new_scaling(8.0);
+    let mut camera = initial_camera(&level, &config.camera);
+    // worked out again whenever the window changes size, the drawable can be bigger than the
+    // window on high DPI displays
+    let mut view = View::new(window.size(), window.drawable_size(), scale_mode);
+    // where the mouse last was in window coordinates, for zooming towards it
+    let mut mouse = na::Vector2::new(config.window.width as f32, config.window.height as f32) / 2.0;
+    let mut last_frame = std::time::Instant::now();
     let mut drawing_wireframe = false;
+    let mut drawing = or_exit(DrawingContext::new(
+        std::path::Path::new(SHADER_DIR),
+        &capabilities,
+    ));
+    if let Some(e) = drawing.shader_error() {
+        println!("Using the built in shaders: {}", e);
+    }
+    // drawing stats go in the title, updated once a second so they're readable
+    let mut last_title_update = std::time::Instant::now();
+    let mut frames_since_title_update = 0;
 
-    // TODO figure out a way to duplicate the keyboard state for "is_just_pressed" functionality
-    let mut jump_pressed_last_frame = false;
-    let mut jump_pressed = false;
+    let mut input = InputState::new(load_bindings());
+    // controllers stop sending events once these are dropped
+    let mut controllers: Vec<GameController> = Vec::new();
+    // a jump press has to survive until a physics step actually happens to see it
+    let mut jump_requested = false;
+
+    let clock = SystemClock::new();
+    let mut timestep = FixedTimestep::new(
+        world.config().integration_parameters.dt,
+        config.physics.max_substeps,
+    );
 
     'running: loop {
         // handle events
         for event in event_pump.poll_iter() {
+            if let Some(input_event) = to_input_event(&event) {
+                input.handle_event(input_event);
+            }
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'running,
+                Event::Quit { .. } => break 'running,
 
-                // player jumping
-                Event::KeyDown {
-                    keycode: Some(Keycode::W),
-                    ..
-                } => jump_pressed = true,
-                Event::KeyUp {
-                    keycode: Some(Keycode::W),
-                    ..
-                } => jump_pressed = false,
-
-                // debug wireframe mode
-                #[cfg(debug_assertions)]
-                Event::KeyDown {
-                    keycode: Some(Keycode::Z),
-                    ..
-                } => {
-                    drawing_wireframe = !drawing_wireframe;
-                    unsafe {
-                        if drawing_wireframe {
-                            gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
-                        } else {
-                            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
-                        }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match controller_subsystem.open(which) {
+                        Ok(controller) => controllers.push(controller),
+                        Err(e) => println!("Failed to open controller {}: {}", which, e),
                     }
                 }
 
-                // resize the gl canvas with the window
-                Event::Window { win_event, .. } => match win_event {
-                    sdl2::event::WindowEvent::Resized(x, y) => unsafe {
-                        gl::Viewport(0, 0, x, y);
-                        projection.set_right(x as f32);
-                        projection.set_bottom(y as f32);
-                    },
-                    _ => {}
-                },
+                Event::MouseMotion { x, y, .. } => mouse = na::Vector2::new(x as f32, y as f32),
+                Event::MouseWheel { y, .. } => {
+                    camera.zoom_by(y as f32, view.window_to_screen(mouse), view.screen_size)
+                }
+
+                // the event's size is in window coordinates, which isn't enough on high DPI
+                // displays, so both sizes are asked for again
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => view = View::new(window.size(), window.drawable_size(), scale_mode),
                 _ => {}
             }
         }
+        if input.just_pressed(Action::Quit) {
+            break 'running;
+        }
 
-        // physics process
-        pipeline.step(
-            &gravity,
-            &integration_parameters,
-            &mut broad_phase,
-            &mut narrow_phase,
-            &mut bodies,
-            &mut colliders,
-            &mut joints,
-            &mut ccd_solver,
-            &physics_hooks,
-            &event_handler,
-        );
-        query.update(&bodies, &colliders);
-        {
-            let horizontal_movement = event_pump
-                .keyboard_state()
-                .is_scancode_pressed(sdl2::keyboard::Scancode::D)
-                as i32 as f64
-                - event_pump
-                    .keyboard_state()
-                    .is_scancode_pressed(sdl2::keyboard::Scancode::A) as i32
-                    as f64;
-            let circle_body = bodies.get_mut(circle_ref).unwrap();
-            circle_body.apply_force(V2::new(500.0 * horizontal_movement, 0.0), true);
-            if !jump_pressed_last_frame
-                && jump_pressed
-                && query
-                    .cast_ray(
-                        &colliders,
-                        &rapier2d_f64::geometry::Ray::new(
-                            na::Point2::from(circle_body.position().translation.vector),
-                            V2::new(0.0, 1.0),
-                        ),
-                        1.5,
-                        true,
-                        rapier2d_f64::geometry::InteractionGroups::all(),
-                        Some(&|ch, c| ch != circle_collider_handle),
-                    )
-                    .is_some()
-            {
-                circle_body.apply_impulse(V2::new(0.0, -200.0), true);
+        // debug wireframe mode, which GLES doesn't have
+        #[cfg(debug_assertions)]
+        if input.just_pressed(Action::ToggleWireframe) && !capabilities.backend.is_gles() {
+            drawing_wireframe = !drawing_wireframe;
+            unsafe {
+                if drawing_wireframe {
+                    gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+                } else {
+                    gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+                }
             }
         }
 
-        // draw
+        if input.just_pressed(Action::ToggleCameraRotation) {
+            camera.planet_up = !camera.planet_up;
+        }
 
-        unsafe {
-            gl::ClearColor(1.0, 1.0, 1.0, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
+        if input.just_pressed(Action::QuickSave) {
+            match world.save_snapshot(&camera, QUICKSAVE_PATH) {
+                Ok(()) => println!("Saved to {}", QUICKSAVE_PATH),
+                Err(e) => println!("{}", e),
+            }
+        }
+        // a recording is only its inputs, a world loaded part way through would play back as
+        // something else entirely
+        if input.just_pressed(Action::QuickLoad) && recording.is_some() {
+            println!("Can't quickload while recording");
+        } else if input.just_pressed(Action::QuickLoad) {
+            match World::load_snapshot(QUICKSAVE_PATH) {
+                Ok((loaded_world, loaded_camera)) => {
+                    world = loaded_world;
+                    camera = loaded_camera;
+                }
+                Err(e) => println!("{}", e),
+            }
+        }
 
-            let qd = DrawingContext {
-                projection: &projection.as_matrix(),
-                camera: &camera,
+        // physics process
+        jump_requested |= input.just_pressed(Action::Jump);
+        let horizontal_movement = input.axis(Action::MoveLeft, Action::MoveRight);
+        for _ in 0..timestep.advance(&clock) {
+            let step_input = Input {
+                horizontal_movement,
+                jump: jump_requested,
+                jump_held: input.pressed(Action::Jump),
             };
-            for (_handle, body) in bodies.iter() {
-                if body.colliders().len() <= 0 {
-                    continue;
-                }
-                match colliders
-                    .get(body.colliders()[0])
-                    .unwrap()
-                    .shape()
-                    .as_typed_shape()
-                {
-                    rapier2d_f64::geometry::TypedShape::Ball(ball) => {
-                        qd.draw_circle(
-                            na::convert(body.position().translation.vector),
-                            ball.radius as f32,
-                        );
-                    }
-                    rapier2d_f64::geometry::TypedShape::Cuboid(cube) => {
-                        qd.draw_rect_rot(
-                            na::convert(body.position().translation.vector - cube.half_extents),
-                            na::convert(body.position().translation.vector + cube.half_extents),
-                            body.position().rotation.angle() as f32,
-                        );
-                    }
-                    _ => (),
-                }
+            if let Some(recording) = recording.as_mut() {
+                recording.record(&step_input);
             }
+            world.step(&step_input);
+            jump_requested = false;
+        }
+        let alpha = timestep.alpha();
+
+        // draw
+        let dt = last_frame.elapsed().as_secs_f32();
+        last_frame = std::time::Instant::now();
+        camera.update(&world, alpha, dt);
+
+        use_view(&view, window.drawable_size());
+        drawing.begin_frame(&view.projection(), &camera.matrix(view.screen_size), WHITE);
+        if drawing.reload_shaders() {
+            match drawing.shader_error() {
+                Some(e) => println!("{}", e),
+                None => println!("Reloaded shaders"),
+            }
+        }
+        draw_world(&world, &mut drawing, alpha);
+        if let Some(error) = drawing.shader_error().map(str::to_string) {
+            // on top of everything, in screen coordinates
+            drawing.set_camera(&na::Matrix4::identity());
+            draw_error(&mut drawing, &error, view.screen_size);
+        }
+        let stats = drawing.end_frame();
+        if input.just_pressed(Action::Screenshot) {
+            let (width, height) = window.drawable_size();
+            let pixels = drawing.read_pixels(width, height);
+            let millis = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis())
+                .unwrap_or(0);
+            let path = format!("screenshot_{}.png", millis);
+            match screenshot::save_png(&path, width as usize, height as usize, &pixels) {
+                Ok(()) => println!("Saved {}", path),
+                Err(e) => println!("{}", e),
+            }
+        }
+        frames_since_title_update += 1;
+        if last_title_update.elapsed() >= std::time::Duration::from_secs(1) {
+            let fps = frames_since_title_update as f64 / last_title_update.elapsed().as_secs_f64();
+            last_title_update = std::time::Instant::now();
+            frames_since_title_update = 0;
+            let _ = window.set_title(&format!(
+                "{} ({:.0} fps, {} draw calls, {} shapes, {} vertices)",
+                config.window.title, fps, stats.draw_calls, stats.shapes, stats.vertices
+            ));
         }
         window.gl_swap_window();
 
-        jump_pressed_last_frame = event_pump
-            .keyboard_state()
-            .is_scancode_pressed(sdl2::keyboard::Scancode::W);
-        // idle
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60)); // TODO take exactly 1/60s every time by accounting for how long computation above takes
+        input.end_frame();
+        // idle until there's something new to simulate
+        std::thread::sleep(timestep.time_until_next_step());
+    }
+    // everything owning GL objects should be gone by now, the context goes right after this
+    drop(drawing);
+    #[cfg(debug_assertions)]
+    if let Err(e) = gl_resources::check_leaks() {
+        eprintln!("{}", e);
+    }
+
+    if let (Some(recording), Some(path)) = (recording, args.record) {
+        match recording.save(&path) {
+            Ok(()) => println!("Saved recording to {}", path),
+            Err(e) => println!("{}", e),
+        }
     }
 }
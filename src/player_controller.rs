@@ -0,0 +1,566 @@
+use crate::util::*;
+use crate::world::Input;
+
+use rapier2d_f64::dynamics::{RigidBodyHandle, RigidBodySet};
+use rapier2d_f64::geometry::{Ball, ColliderHandle, ColliderSet, InteractionGroups};
+use rapier2d_f64::na::Isometry2;
+use rapier2d_f64::parry::query::TOIStatus;
+use rapier2d_f64::pipeline::QueryPipeline;
+use serde::{Deserialize, Serialize};
+
+/// Tunables for how the player moves
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControllerConfig {
+    pub move_force: f64,
+    /// Fraction of `move_force` available while in the air
+    pub air_control: f64,
+    /// Fastest the player can make themselves move along the ground
+    pub max_speed: f64,
+    pub jump_impulse: f64,
+    /// Seconds after walking off a ledge that jumping still works
+    pub coyote_time: f64,
+    /// Seconds before landing that pressing jump still counts
+    pub jump_buffer_time: f64,
+    /// Upward speed is multiplied by this when jump is let go of early, for shorter hops
+    pub jump_cut: f64,
+    /// Steepest ground, in radians from straight up, that still counts as standing on something
+    pub max_slope: f64,
+    /// How far below the player's collider to look for ground
+    pub ground_probe_distance: f64,
+}
+
+impl Default for ControllerConfig {
+    fn default() -> Self {
+        ControllerConfig {
+            move_force: 500.0,
+            air_control: 0.5,
+            max_speed: 40.0,
+            jump_impulse: 200.0,
+            coyote_time: 0.1,
+            jump_buffer_time: 0.1,
+            jump_cut: 0.5,
+            max_slope: 50.0f64.to_radians(),
+            ground_probe_distance: 0.2,
+        }
+    }
+}
+
+/// What the player was last found standing on
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Ground {
+    pub collider: ColliderHandle,
+    /// Outward surface normal in world space
+    pub normal: V2,
+    /// Angle in radians between the surface normal and the player's up
+    pub slope: f64,
+}
+
+/// Turns `Input` into forces on the player's body, relative to whichever way gravity is pulling
+#[derive(Serialize, Deserialize)]
+pub struct PlayerController {
+    config: ControllerConfig,
+    body: RigidBodyHandle,
+    collider: ColliderHandle,
+
+    ground: Option<Ground>,
+    /// Seconds since the player last stood on walkable ground, `f64::MAX` when that shouldn't
+    /// count for anything
+    time_since_grounded: f64,
+    /// Seconds since jump was last pressed, `None` once that press has been used up
+    time_since_jump_pressed: Option<f64>,
+    /// In the rising part of a jump that can still be cut short
+    jumping: bool,
+}
+
+impl PlayerController {
+    pub fn new(body: RigidBodyHandle, collider: ColliderHandle, config: ControllerConfig) -> Self {
+        PlayerController {
+            config,
+            body,
+            collider,
+            ground: None,
+            time_since_grounded: f64::MAX,
+            time_since_jump_pressed: None,
+            jumping: false,
+        }
+    }
+
+    /// Should be called once before every physics step
+    ///
+    /// # Arguments
+    ///
+    /// * `dt` - Length of the step about to happen
+    /// * `down` - Unit vector in the direction gravity pulls on the player
+    pub fn update(
+        &mut self,
+        input: &Input,
+        dt: f64,
+        down: V2,
+        bodies: &mut RigidBodySet,
+        colliders: &ColliderSet,
+        query: &QueryPipeline,
+    ) {
+        let up = -down;
+        // right is down rotated a quarter turn counter clockwise, in y down screen space
+        let right = V2::new(down.y, -down.x);
+
+        self.ground = self.probe_ground(up, bodies, colliders, query);
+        match self.ground {
+            Some(ground) if ground.slope <= self.config.max_slope => self.time_since_grounded = 0.0,
+            _ => self.time_since_grounded += dt,
+        }
+        let on_ground = self.time_since_grounded == 0.0;
+
+        if input.jump {
+            self.time_since_jump_pressed = Some(0.0);
+        } else if let Some(t) = self.time_since_jump_pressed.as_mut() {
+            *t += dt;
+        }
+
+        let body = bodies.get_mut(self.body).unwrap();
+        let mut linvel = *body.linvel();
+
+        // walk along the ground's surface so slopes don't launch or stall the player
+        let along = match self.ground {
+            Some(ground) if on_ground => V2::new(-ground.normal.y, ground.normal.x),
+            _ => right,
+        };
+        let along = if along.dot(&right) < 0.0 {
+            -along
+        } else {
+            along
+        };
+        let control = if on_ground {
+            1.0
+        } else {
+            self.config.air_control
+        };
+        let speed = linvel.dot(&along);
+        let wanted = input.horizontal_movement.clamp(-1.0, 1.0);
+        // only push if it doesn't take the player past max speed
+        if wanted * speed < self.config.max_speed * wanted.abs() {
+            body.apply_force(along * wanted * control * self.config.move_force, true);
+        }
+        if speed.abs() > self.config.max_speed {
+            linvel -= along * (speed - self.config.max_speed * speed.signum());
+        }
+
+        let rising = linvel.dot(&up) > 0.0;
+        if self.jumping && !rising {
+            self.jumping = false;
+        }
+        if self.jumping && !input.jump_held {
+            linvel -= up * linvel.dot(&up) * (1.0 - self.config.jump_cut);
+            self.jumping = false;
+        }
+
+        let jump_buffered = self
+            .time_since_jump_pressed
+            .is_some_and(|t| t <= self.config.jump_buffer_time);
+        let can_jump = self.time_since_grounded <= self.config.coyote_time && !self.jumping;
+        if jump_buffered && can_jump {
+            // falling speed shouldn't eat into the jump
+            let falling = linvel.dot(&down).max(0.0);
+            linvel -= down * falling;
+            body.set_linvel(linvel, true);
+            body.apply_impulse(up * self.config.jump_impulse, true);
+            self.time_since_jump_pressed = None;
+            self.time_since_grounded = f64::MAX;
+            self.jumping = true;
+        } else if linvel != *body.linvel() {
+            body.set_linvel(linvel, true);
+        }
+    }
+
+    fn probe_ground(
+        &self,
+        up: V2,
+        bodies: &RigidBodySet,
+        colliders: &ColliderSet,
+        query: &QueryPipeline,
+    ) -> Option<Ground> {
+        let body = &bodies[self.body];
+        let radius = colliders[self.collider]
+            .shape()
+            .compute_local_bounding_sphere()
+            .radius;
+        // cast a slightly smaller ball so it doesn't start out already touching the ground
+        let probe = Ball::new(radius * 0.9);
+        let own_collider = self.collider;
+        // triggers aren't ground, and the player overlaps them, which would count as standing
+        let (collider, toi) = query.cast_shape(
+            colliders,
+            &Isometry2::new(body.world_com.coords, 0.0),
+            &-up,
+            &probe,
+            radius * 0.1 + self.config.ground_probe_distance,
+            InteractionGroups::all(),
+            Some(&|ch, c| ch != own_collider && !c.is_sensor()),
+        )?;
+        // the normal is meaningless when already overlapping, but it must be something like up
+        let normal = match toi.status {
+            TOIStatus::Penetrating => up,
+            _ => toi.normal1.into_inner(),
+        };
+        Some(Ground {
+            collider,
+            normal,
+            slope: normal.dot(&up).clamp(-1.0, 1.0).acos(),
+        })
+    }
+
+    pub fn ground(&self) -> Option<&Ground> {
+        self.ground.as_ref()
+    }
+
+    /// Standing on walkable ground as of the last update
+    pub fn is_grounded(&self) -> bool {
+        self.time_since_grounded == 0.0
+    }
+
+    pub fn body(&self) -> RigidBodyHandle {
+        self.body
+    }
+
+    pub fn collider(&self) -> ColliderHandle {
+        self.collider
+    }
+
+    pub fn config(&self) -> &ControllerConfig {
+        &self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rapier2d_f64::dynamics::{CCDSolver, IntegrationParameters, JointSet, RigidBodyBuilder};
+    use rapier2d_f64::geometry::{BroadPhase, ColliderBuilder, NarrowPhase, SharedShape};
+    use rapier2d_f64::pipeline::PhysicsPipeline;
+
+    fn down() -> V2 {
+        V2::new(0.0, 1.0)
+    }
+
+    /// Ground through the origin, tilted by `ground_angle`, with the player dropped in just above
+    /// it under plain downwards gravity
+    struct TestWorld {
+        pipeline: PhysicsPipeline,
+        query: QueryPipeline,
+        broad_phase: BroadPhase,
+        narrow_phase: NarrowPhase,
+        bodies: RigidBodySet,
+        colliders: ColliderSet,
+        joints: JointSet,
+        ccd_solver: CCDSolver,
+        integration_parameters: IntegrationParameters,
+        controller: PlayerController,
+    }
+
+    impl TestWorld {
+        fn new(ground_angle: f64, config: ControllerConfig) -> Self {
+            let mut bodies = RigidBodySet::new();
+            let mut colliders = ColliderSet::new();
+            let ground_center = Isometry2::rotation(ground_angle) * V2::new(0.0, 10.0);
+            let ground = bodies.insert(
+                RigidBodyBuilder::new_static()
+                    .position(Isometry2::new(ground_center, ground_angle))
+                    .build(),
+            );
+            colliders.insert(
+                ColliderBuilder::new(SharedShape::cuboid(100.0, 10.0)).build(),
+                ground,
+                &mut bodies,
+            );
+            let player = bodies.insert(
+                RigidBodyBuilder::new_dynamic()
+                    .position(Isometry2::new(V2::new(0.0, -3.0), 0.0))
+                    .build(),
+            );
+            let collider = colliders.insert(
+                ColliderBuilder::new(SharedShape::ball(1.0))
+                    .restitution(0.0)
+                    .build(),
+                player,
+                &mut bodies,
+            );
+            let mut world = TestWorld {
+                pipeline: PhysicsPipeline::new(),
+                query: QueryPipeline::new(),
+                broad_phase: BroadPhase::new(),
+                narrow_phase: NarrowPhase::new(),
+                bodies,
+                colliders,
+                joints: JointSet::new(),
+                ccd_solver: CCDSolver::new(),
+                integration_parameters: IntegrationParameters::default(),
+                controller: PlayerController::new(player, collider, config),
+            };
+            world.query.update(&world.bodies, &world.colliders);
+            world
+        }
+
+        fn step(&mut self, input: Input) {
+            self.controller.update(
+                &input,
+                self.integration_parameters.dt,
+                down(),
+                &mut self.bodies,
+                &self.colliders,
+                &self.query,
+            );
+            self.pipeline.step(
+                &(down() * 50.0),
+                &self.integration_parameters,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.bodies,
+                &mut self.colliders,
+                &mut self.joints,
+                &mut self.ccd_solver,
+                &(),
+                &(),
+            );
+            self.query.update(&self.bodies, &self.colliders);
+        }
+
+        fn steps(&mut self, n: usize, input: Input) {
+            for _ in 0..n {
+                self.step(input);
+            }
+        }
+
+        fn player_velocity(&self) -> V2 {
+            *self.bodies[self.controller.body()].linvel()
+        }
+
+        fn player_position(&self) -> V2 {
+            self.bodies[self.controller.body()]
+                .position()
+                .translation
+                .vector
+        }
+    }
+
+    fn jump() -> Input {
+        Input {
+            jump: true,
+            jump_held: true,
+            ..Input::default()
+        }
+    }
+
+    fn hold_jump() -> Input {
+        Input {
+            jump_held: true,
+            ..Input::default()
+        }
+    }
+
+    fn walk(dir: f64) -> Input {
+        Input {
+            horizontal_movement: dir,
+            ..Input::default()
+        }
+    }
+
+    #[test]
+    fn detects_flat_ground() {
+        let mut world = TestWorld::new(0.0, ControllerConfig::default());
+        world.steps(30, Input::default());
+        assert!(world.controller.is_grounded());
+        let ground = world.controller.ground().unwrap();
+        assert!((ground.normal - V2::new(0.0, -1.0)).norm() < 1e-3);
+        assert!(ground.slope < 1e-3);
+    }
+
+    #[test]
+    fn triggers_are_not_ground() {
+        let mut world = TestWorld::new(0.0, ControllerConfig::default());
+        // around where the player starts, well clear of the ground
+        let trigger = world.bodies.insert(
+            RigidBodyBuilder::new_static()
+                .position(Isometry2::new(V2::new(0.0, -3.0), 0.0))
+                .build(),
+        );
+        world.colliders.insert(
+            ColliderBuilder::new(SharedShape::cuboid(5.0, 2.0))
+                .sensor(true)
+                .build(),
+            trigger,
+            &mut world.bodies,
+        );
+        world.query.update(&world.bodies, &world.colliders);
+        for _ in 0..5 {
+            world.step(jump());
+            assert!(!world.controller.is_grounded());
+        }
+        assert!(world.player_velocity().y > 0.0, "jumped in mid air");
+        world.steps(60, Input::default());
+        assert!(world.controller.is_grounded());
+    }
+
+    #[test]
+    fn tracks_slope() {
+        let angle = 20.0f64.to_radians();
+        let mut world = TestWorld::new(angle, ControllerConfig::default());
+        world.steps(60, Input::default());
+        let ground = world.controller.ground().unwrap();
+        assert!((ground.slope - angle).abs() < 1e-3);
+        assert!(world.controller.is_grounded());
+
+        // too steep to stand on
+        let config = ControllerConfig {
+            max_slope: 10.0f64.to_radians(),
+            ..ControllerConfig::default()
+        };
+        let mut world = TestWorld::new(angle, config);
+        world.steps(60, Input::default());
+        assert!(world.controller.ground().is_some());
+        assert!(!world.controller.is_grounded());
+    }
+
+    #[test]
+    fn jumps_from_ground_only() {
+        let mut world = TestWorld::new(0.0, ControllerConfig::default());
+        world.steps(30, Input::default());
+        world.step(jump());
+        assert!(world.player_velocity().y < 0.0);
+        assert!(!world.controller.is_grounded());
+
+        // no double jumping
+        world.steps(10, hold_jump());
+        let before = world.player_velocity().y;
+        world.step(jump());
+        assert!(world.player_velocity().y >= before);
+    }
+
+    #[test]
+    fn coyote_time() {
+        let config = ControllerConfig::default();
+        let coyote_steps = (config.coyote_time / IntegrationParameters::default().dt) as usize;
+
+        let mut world = TestWorld::new(0.0, config.clone());
+        world.steps(30, Input::default());
+        // pretend the ground vanished
+        let player = world.controller.body();
+        world
+            .bodies
+            .get_mut(player)
+            .unwrap()
+            .set_position(Isometry2::new(V2::new(0.0, -100.0), 0.0), true);
+        world.query.update(&world.bodies, &world.colliders);
+        world.steps(coyote_steps - 1, Input::default());
+        world.step(jump());
+        assert!(world.player_velocity().y < 0.0);
+
+        let mut world = TestWorld::new(0.0, config);
+        world.steps(30, Input::default());
+        world
+            .bodies
+            .get_mut(player)
+            .unwrap()
+            .set_position(Isometry2::new(V2::new(0.0, -100.0), 0.0), true);
+        world.query.update(&world.bodies, &world.colliders);
+        world.steps(coyote_steps + 2, Input::default());
+        world.step(jump());
+        assert!(world.player_velocity().y > 0.0);
+    }
+
+    #[test]
+    fn jump_buffering() {
+        let mut world = TestWorld::new(0.0, ControllerConfig::default());
+        world.steps(30, Input::default());
+        world.step(jump());
+        // wait for the way down, then press jump just before landing
+        let mut steps = 0;
+        while world.player_position().y < -2.5 || world.player_velocity().y < 0.0 {
+            world.step(hold_jump());
+            steps += 1;
+            assert!(steps < 1000);
+        }
+        world.step(jump());
+        world.steps(2, hold_jump());
+        let mut jumped_again = false;
+        for _ in 0..10 {
+            world.step(hold_jump());
+            jumped_again |= world.player_velocity().y < -10.0;
+        }
+        assert!(jumped_again);
+    }
+
+    fn jump_height(hold_for: usize) -> f64 {
+        let mut world = TestWorld::new(0.0, ControllerConfig::default());
+        world.steps(30, Input::default());
+        let start = world.player_position().y;
+        world.step(jump());
+        let mut highest = start;
+        for i in 0..300 {
+            world.step(if i < hold_for {
+                hold_jump()
+            } else {
+                Input::default()
+            });
+            highest = highest.min(world.player_position().y);
+        }
+        start - highest
+    }
+
+    #[test]
+    fn variable_jump_height() {
+        let short = jump_height(5);
+        let full = jump_height(300);
+        assert!(short > 0.5);
+        assert!(short < full * 0.6, "short {} full {}", short, full);
+    }
+
+    #[test]
+    fn max_speed() {
+        let config = ControllerConfig::default();
+        let max_speed = config.max_speed;
+        let mut world = TestWorld::new(0.0, config);
+        world.steps(30, Input::default());
+        world.steps(300, walk(1.0));
+        assert!(world.player_velocity().x > max_speed * 0.9);
+        assert!(world.player_velocity().x <= max_speed + 1e-6);
+
+        world.steps(600, walk(-1.0));
+        assert!(world.player_velocity().x < -max_speed * 0.9);
+        assert!(world.player_velocity().x >= -max_speed - 1e-6);
+    }
+
+    fn air_speed(air_control: f64) -> f64 {
+        let config = ControllerConfig {
+            air_control,
+            ..ControllerConfig::default()
+        };
+        let mut world = TestWorld::new(0.0, config);
+        world.steps(30, Input::default());
+        world.step(jump());
+        world.steps(5, hold_jump());
+        world.steps(
+            10,
+            Input {
+                horizontal_movement: 1.0,
+                jump_held: true,
+                ..Input::default()
+            },
+        );
+        world.player_velocity().x
+    }
+
+    #[test]
+    fn air_control() {
+        assert!(air_speed(0.0).abs() < 1e-6);
+        let half = air_speed(0.5);
+        let full = air_speed(1.0);
+        assert!(half > 0.0);
+        assert!(
+            (full / half - 2.0).abs() < 1e-3,
+            "half {} full {}",
+            half,
+            full
+        );
+    }
+}
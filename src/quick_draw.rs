@@ -1,149 +1,560 @@
 extern crate gl;
-use crate::gl_shaders::*;
+use crate::gl_backend::Capabilities;
 use crate::gl_vertices::*;
+use crate::render::{Color, DrawStats, RenderBackend, RoundRect, Style};
+use crate::screenshot::flip_rows;
+use crate::shader_assets::ShaderAsset;
+
+use std::path::Path;
 
-type P2 = na::Point2<f32>;
 type V2 = na::Vector2<f32>;
 
-pub struct DrawingContext<'a> {
-    pub projection: &'a na::Matrix4<f32>,
-    pub camera: &'a na::Matrix4<f32>,
+/// Once a batch has this many vertices it's drawn, and a new one is started
+const MAX_BATCH_VERTICES: usize = 1 << 16;
+/// Once this many round rects are waiting they're drawn, and a new batch is started
+const MAX_INSTANCES: usize = 1 << 16;
+/// Marks polygons, which shape.frag fills without fading out their edges. It has to fit in a
+/// `mediump` float, which GLES 2.0 fragment shaders can be stuck with.
+const POLYGON_RADIUS: f32 = -1.0;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShapeVertex {
+    /// In world space
+    pub position: V2,
+    /// Relative to the center of the round rect this is part of, along its sides. Always zero
+    /// for polygons.
+    pub local: V2,
+    pub color: Color,
+    pub outline: Color,
+    /// The rest of the round rect, its edge is worked out for every pixel in the shader
+    pub half_extents: V2,
+    pub radius: f32,
+    pub outline_width: f32,
 }
 
-impl<'a> DrawingContext<'a> {
-    pub fn draw_circle(&self, offset: V2, radius: f32) {
-        let shader_program = shader_inline!(
-            "#version 330 core
-
-            layout (location = 0) in vec2 Position;
-            
-            out vec2 pos;
-            
-            uniform mat4 camera;
-            uniform mat4 projection;
-            uniform vec2 offset;
-            uniform float radius;
-            
-            void main()
-            {
-                pos = Position;
-                gl_Position = projection * camera * vec4(Position * radius + offset, 0.0, 1.0);
-            }
-            ",
-            "#version 330 core
-
-            out vec4 Color;
-            in vec2 pos;
-            
-            void main()
-            {
-                float len = length(pos);
-                if(len > 1.0) {
-                    Color = vec4(0.0);
-                } else {
-                    Color = vec4(0.0, 0.0, 0.0, 1.0);
-                }
-            }
-            "
-        );
+impl_vertex!(ShapeVertex {
+    position: 0,
+    local: 1,
+    color: 2,
+    outline: 3,
+    half_extents: 4,
+    radius: 5,
+    outline_width: 6,
+});
 
-        use vertex_attribs::*;
-        let mut gl_vertices = VertexData::new(vec![VECTOR2_F32]);
+/// Shapes waiting to be drawn, all in one vertex and index buffer
+#[derive(Default)]
+pub struct ShapeBatch {
+    vertices: Vec<ShapeVertex>,
+    indices: Vec<u32>,
+    shapes: u32,
+}
 
-        gl_vertices.append(
-            &mut vec![
-                V2::new(-1.0, -1.0),
-                V2::new(1.0, -1.0),
-                V2::new(1.0, 1.0),
-                V2::new(-1.0, 1.0),
-            ],
-            &mut vec![0, 1, 2, 0, 3, 2],
-            true,
-        );
+impl ShapeBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        shader_program.set_used();
-        shader_program.write_mat4("projection", self.projection);
-        shader_program.write_mat4("camera", self.camera);
-        shader_program.write_vec2("offset", &offset);
-        shader_program.write_float("radius", radius);
-        gl_vertices.draw();
-    }
-    fn rot_mat(t: f32, wrt: V2) -> na::Matrix3<f32> {
-        na::Matrix3::new(
-            t.cos(),
-            -t.sin(),
-            -wrt.x * t.cos() + wrt.y * t.sin() + wrt.x,
-            t.sin(),
-            t.cos(),
-            -wrt.x * t.sin() - wrt.y * t.cos() + wrt.y,
-            0.0,
+    pub fn vertices(&self) -> &[ShapeVertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    pub fn shapes(&self) -> u32 {
+        self.shapes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.shapes = 0;
+    }
+
+    /// Adds a convex polygon made of `corners` in order around its edge, as a triangle fan
+    fn push_fan(&mut self, corners: impl Iterator<Item = ShapeVertex>) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend(corners);
+        let count = self.vertices.len() as u32 - first;
+        for i in 1..count.saturating_sub(1) {
+            self.indices
+                .extend_from_slice(&[first, first + i, first + i + 1]);
+        }
+        self.shapes += 1;
+    }
+
+    pub fn push_circle(&mut self, offset: V2, radius: f32, color: Color) {
+        self.push_round_rect(
+            &RoundRect::circle(offset, radius),
+            &Style::filled(color),
             0.0,
-            1.0,
-        )
-    }
-    pub fn draw_rect(&self, upper_left: V2, lower_right: V2) {
-        self.draw_rect_rot(upper_left, lower_right, 0.0);
-    }
-    /// `rotation` is with respect to the center of the rectangle
-    pub fn draw_rect_rot(&self, upper_left: V2, lower_right: V2, rotation: f32) {
-        let shader_program = shader_inline!(
-            "#version 330 core
-
-            layout (location = 0) in vec2 Position;
-            
-            out vec2 pos;
-            
-            uniform mat4 camera;
-            uniform mat4 projection;
-            
-            void main()
-            {
-                pos = Position;
-                gl_Position = projection * camera * vec4(Position, 0.0, 1.0);
-            }
-            ",
-            "#version 330 core
-
-            out vec4 Color;
-            in vec2 pos;
-            
-            void main()
-            {
-                Color = vec4(0.0, 0.0, 0.0, 1.0);
-            }
-            "
         );
+    }
 
-        use vertex_attribs::*;
-        let mut gl_vertices = VertexData::new(vec![VECTOR2_F32]);
-
-        let width = lower_right.x - upper_left.x;
-        let rotation = Self::rot_mat(rotation, (lower_right + upper_left)/2.0);
-        gl_vertices.append(
-            &mut vec![
-                rotation.transform_point(&P2::from(upper_left)).coords,
-                rotation.transform_point(&P2::from(upper_left + V2::new(width, 0.0))).coords,
-                rotation.transform_point(&P2::from(lower_right)).coords,
-                rotation.transform_point(&P2::from((lower_right - V2::new(width, 0.0)))).coords,
-            ],
-            &mut vec![0, 1, 2, 0, 3, 2],
-            true,
+    /// A quad `padding` bigger than `rect` on every side, so there's room for its edge to fade out
+    pub fn push_round_rect(&mut self, rect: &RoundRect, style: &Style, padding: f32) {
+        let (color, outline) = (style.fill_color(), style.outline_color());
+        let outline_width = if style.outline_width > 0.0 {
+            style.outline_width
+        } else {
+            0.0
+        };
+        self.push_fan(
+            rect.corners(padding)
+                .iter()
+                .map(|&(position, local)| ShapeVertex {
+                    position,
+                    local,
+                    color,
+                    outline,
+                    half_extents: rect.half_extents,
+                    radius: rect.radius,
+                    outline_width,
+                }),
         );
+    }
+
+    pub fn push_polygon(&mut self, points: &[V2], color: Color) {
+        self.push_fan(points.iter().map(|p| ShapeVertex {
+            position: *p,
+            local: V2::zeros(),
+            color,
+            outline: color,
+            half_extents: V2::zeros(),
+            radius: POLYGON_RADIUS,
+            outline_width: 0.0,
+        }));
+    }
+}
+
+/// A corner of the quad every `RoundRectInstance` is drawn on, from -1 to 1 on both axes
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuadCorner {
+    pub corner: [f32; 2],
+}
+
+impl_vertex!(QuadCorner { corner: 0 });
+
+const UNIT_QUAD: [QuadCorner; 4] = [
+    QuadCorner {
+        corner: [-1.0, -1.0],
+    },
+    QuadCorner {
+        corner: [1.0, -1.0],
+    },
+    QuadCorner { corner: [1.0, 1.0] },
+    QuadCorner {
+        corner: [-1.0, 1.0],
+    },
+];
+
+/// A round rect drawn with instancing, the quad around it is worked out in the vertex shader so
+/// each one only takes this much room
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoundRectInstance {
+    pub center: V2,
+    pub half_extents: V2,
+    pub angle: f32,
+    pub radius: f32,
+    pub outline_width: f32,
+    pub color: Color,
+    pub outline: Color,
+}
+
+impl_vertex!(RoundRectInstance {
+    center: 1,
+    half_extents: 2,
+    angle: 3,
+    radius: 4,
+    outline_width: 5,
+    color: 6,
+    outline: 7,
+});
+
+impl RoundRectInstance {
+    pub fn new(rect: &RoundRect, style: &Style) -> Self {
+        RoundRectInstance {
+            center: rect.center,
+            half_extents: rect.half_extents,
+            angle: rect.angle,
+            radius: rect.radius,
+            outline_width: style.outline_width.max(0.0),
+            color: style.fill_color(),
+            outline: style.outline_color(),
+        }
+    }
+}
+
+/// What's needed to draw round rects with instancing
+struct InstancedRoundRects {
+    shader: ShaderAsset,
+    quads: VertexData<QuadCorner, RoundRectInstance>,
+}
+
+/// Collects every shape drawn during a frame and sends them to the GPU in as few draw calls as
+/// possible. Round rects, which is most of what's drawn, are instanced where there's instancing:
+/// however many there are in a row they take a single draw call. The buffers are only created
+/// once, the shader programs again whenever their files change.
+pub struct DrawingContext {
+    shader: ShaderAsset,
+    /// `None` without instancing, round rects go in `batch` like everything else then
+    instanced: Option<InstancedRoundRects>,
+    /// From the last time the shaders' uniforms were set, if it went wrong
+    uniform_error: Option<String>,
+    gl_vertices: VertexData<ShapeVertex>,
+    batch: ShapeBatch,
+    /// Waiting to be drawn. Only ever this or `batch` has anything in it, so everything is drawn
+    /// in the order it came in.
+    round_rects: Vec<RoundRectInstance>,
+    projection: na::Matrix4<f32>,
+    camera: na::Matrix4<f32>,
+    /// In pixels, for the current frame
+    viewport: V2,
+    /// How big a pixel is in world units, for the current frame
+    pixel_size: f32,
+    /// Of the frame being drawn
+    frame_stats: DrawStats,
+    stats: DrawStats,
+}
+
+impl DrawingContext {
+    /// Loads the shaders from `shader_dir`, falling back to the built in ones if they're missing
+    /// or broken. Needs a current GL context, which `capabilities` describe.
+    pub fn new(shader_dir: &Path, capabilities: &Capabilities) -> Result<Self, String> {
+        let shader = ShaderAsset::load(
+            shader_dir,
+            ("shape.vert", "shape.frag"),
+            (
+                include_str!("../assets/shaders/shape.vert"),
+                include_str!("../assets/shaders/shape.frag"),
+            ),
+            capabilities.backend,
+        )?;
+        let instanced = if capabilities.instancing {
+            let shader = ShaderAsset::load(
+                shader_dir,
+                ("round_rect.vert", "shape.frag"),
+                (
+                    include_str!("../assets/shaders/round_rect.vert"),
+                    include_str!("../assets/shaders/shape.frag"),
+                ),
+                capabilities.backend,
+            )?;
+            let mut quads = VertexData::new(BufferUsage::Stream, capabilities);
+            quads.set_primitive(Primitive::TriangleFan);
+            quads.replace(&UNIT_QUAD, &[]);
+            Some(InstancedRoundRects { shader, quads })
+        } else {
+            None
+        };
+
+        Ok(DrawingContext {
+            shader,
+            instanced,
+            uniform_error: None,
+            gl_vertices: VertexData::new(BufferUsage::Stream, capabilities),
+            batch: ShapeBatch::new(),
+            round_rects: Vec::new(),
+            projection: na::Matrix4::identity(),
+            camera: na::Matrix4::identity(),
+            viewport: V2::zeros(),
+            pixel_size: 0.0,
+            frame_stats: DrawStats::default(),
+            stats: DrawStats::default(),
+        })
+    }
+
+    /// Compiles the shaders again if their files changed, returns whether they did. When they
+    /// don't compile the old ones are kept, see `shader_error`.
+    pub fn reload_shaders(&mut self) -> bool {
+        // both, even if the first one changed
+        let mut reloaded = self.shader.reload_if_changed();
+        if let Some(instanced) = &mut self.instanced {
+            reloaded |= instanced.shader.reload_if_changed();
+        }
+        if reloaded {
+            self.uniform_error = None;
+        }
+        reloaded
+    }
+
+    /// The compile or link log of the shader files if they're broken, or why the uniforms
+    /// couldn't be set
+    pub fn shader_error(&self) -> Option<&str> {
+        self.shader
+            .error()
+            .or_else(|| self.instanced.as_ref()?.shader.error())
+            .or(self.uniform_error.as_deref())
+    }
 
-        shader_program.set_used();
-        shader_program.write_mat4("projection", self.projection);
-        shader_program.write_mat4("camera", self.camera);
-        gl_vertices.draw();
+    /// Draws everything so far, and what comes after with a different camera. Useful for
+    /// drawing things that stay put on screen over the world.
+    pub fn set_camera(&mut self, camera: &na::Matrix4<f32>) {
+        self.flush();
+        self.camera = *camera;
+        self.pixel_size = pixel_size(&(self.projection * camera), self.viewport);
+    }
+
+    /// The stats of the last finished frame
+    pub fn stats(&self) -> DrawStats {
+        self.stats
+    }
+
+    /// Draws everything batched so far right away
+    pub fn flush(&mut self) {
+        self.flush_round_rects();
+        self.flush_batch();
+    }
+
+    fn flush_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        self.frame_stats.draw_calls += 1;
+        self.frame_stats.shapes += self.batch.shapes();
+        self.frame_stats.vertices += self.batch.vertices().len() as u32;
+
+        self.gl_vertices
+            .replace(self.batch.vertices(), self.batch.indices());
+        self.batch.clear();
+
+        let program = self.shader.program();
+        program.set_used();
+        let written = program
+            .set_uniform("projection", &self.projection)
+            .and_then(|()| program.set_uniform("camera", &self.camera));
+        if let Err(e) = written {
+            self.uniform_error = Some(e);
+        }
+        self.gl_vertices.draw();
+    }
+
+    fn flush_round_rects(&mut self) {
+        let instanced = match &mut self.instanced {
+            Some(instanced) if !self.round_rects.is_empty() => instanced,
+            _ => return,
+        };
+        self.frame_stats.draw_calls += 1;
+        self.frame_stats.shapes += self.round_rects.len() as u32;
+        self.frame_stats.vertices += (self.round_rects.len() * UNIT_QUAD.len()) as u32;
+
+        instanced.quads.replace_instances(&self.round_rects);
+        self.round_rects.clear();
+
+        let (camera, padding) = (self.camera, self.pixel_size);
+        let program = instanced.shader.program();
+        program.set_used();
+        let written = program
+            .set_uniform("projection", &self.projection)
+            .and_then(|()| program.set_uniform("camera", &camera))
+            .and_then(|()| program.set_uniform("padding", &padding));
+        if let Err(e) = written {
+            self.uniform_error = Some(e);
+        }
+        instanced.quads.draw();
+    }
+
+    /// Reads back what's been drawn to the window so far, as RGBA rows from the top down
+    pub fn read_pixels(&self, width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+        unsafe {
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr() as *mut gl::types::GLvoid,
+            );
+        }
+        flip_rows(&mut pixels, width as usize);
+        pixels
+    }
+
+    fn make_room(&mut self, vertices: usize) {
+        self.flush_round_rects();
+        if self.batch.vertices().len() + vertices > MAX_BATCH_VERTICES {
+            self.flush_batch();
+        }
+    }
+}
+
+/// How big a pixel is in world units, when `clip` takes world coordinates to clip space and the
+/// viewport is `viewport` pixels big
+fn pixel_size(clip: &na::Matrix4<f32>, viewport: V2) -> f32 {
+    // how far one step along the world's x axis goes on screen, clip space being 2 across. Pixels
+    // are square, so any other direction would do just as well.
+    let scale = V2::new(clip[(0, 0)] * viewport.x, clip[(1, 0)] * viewport.y).norm() / 2.0;
+    if scale > 0.0 {
+        1.0 / scale
+    } else {
+        0.0
+    }
+}
+
+impl RenderBackend for DrawingContext {
+    fn begin_frame(
+        &mut self,
+        projection: &na::Matrix4<f32>,
+        camera: &na::Matrix4<f32>,
+        clear: Color,
+    ) {
+        self.projection = *projection;
+        self.camera = *camera;
+        let mut viewport = [0; 4];
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+        self.viewport = V2::new(viewport[2] as f32, viewport[3] as f32);
+        self.pixel_size = pixel_size(&(projection * camera), self.viewport);
+        self.batch.clear();
+        self.round_rects.clear();
+        self.frame_stats = DrawStats::default();
+        unsafe {
+            gl::ClearColor(clear[0], clear[1], clear[2], clear[3]);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+    }
+
+    fn fill_round_rect(&mut self, rect: &RoundRect, style: &Style) {
+        if self.instanced.is_none() {
+            self.make_room(4);
+            self.batch.push_round_rect(rect, style, self.pixel_size);
+            return;
+        }
+        self.flush_batch();
+        if self.round_rects.len() >= MAX_INSTANCES {
+            self.flush_round_rects();
+        }
+        self.round_rects.push(RoundRectInstance::new(rect, style));
+    }
+
+    fn fill_polygon(&mut self, points: &[V2], color: Color) {
+        self.make_room(points.len());
+        self.batch.push_polygon(points, color);
+    }
+
+    fn end_frame(&mut self) -> DrawStats {
+        self.flush();
+        self.stats = self.frame_stats;
+        self.stats
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::render::BLACK;
 
     #[test]
-    fn rot_mat_works() {
-        let rot = DrawingContext::rot_mat(-1.5708, V2::new(1.0, 0.0));
-        assert_eq!(rot.transform_point(&P2::new(1.0, 1.0)), P2::new(2.0, 0.0));
+    fn batches_shapes_together() {
+        let mut batch = ShapeBatch::new();
+        batch.push_circle(V2::new(10.0, 0.0), 2.0, BLACK);
+        batch.push_polygon(
+            &[
+                V2::new(0.0, 0.0),
+                V2::new(4.0, 0.0),
+                V2::new(4.0, 2.0),
+                V2::new(0.0, 2.0),
+            ],
+            BLACK,
+        );
+        batch.push_polygon(
+            &[V2::new(0.0, 0.0), V2::new(1.0, 0.0), V2::new(0.0, 1.0)],
+            BLACK,
+        );
+        assert_eq!(batch.shapes(), 3);
+        assert_eq!(batch.vertices().len(), 11);
+        assert_eq!(
+            batch.indices(),
+            &[0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7, 8, 9, 10]
+        );
+
+        assert_eq!(batch.vertices()[0].position, V2::new(8.0, -2.0));
+        assert_eq!(batch.vertices()[2].position, V2::new(12.0, 2.0));
+        assert_eq!(batch.vertices()[2].local, V2::new(2.0, 2.0));
+        assert_eq!(batch.vertices()[5].position, V2::new(4.0, 0.0));
+        assert_eq!(batch.vertices()[2].radius, 2.0);
+        assert!(batch.vertices()[4..].iter().all(|v| v.local == V2::zeros()));
+        assert!(batch.vertices()[4..]
+            .iter()
+            .all(|v| v.radius == POLYGON_RADIUS));
+
+        // padded on every side
+        let rect = RoundRect {
+            center: V2::new(1.0, 0.0),
+            half_extents: V2::new(2.0, 1.0),
+            angle: 0.0,
+            radius: 0.5,
+        };
+        batch.push_round_rect(&rect, &Style::outlined(BLACK, BLACK, 0.1), 0.5);
+        assert_eq!(batch.vertices()[11].position, V2::new(-2.0, -2.0));
+        assert_eq!(batch.vertices()[11].local, V2::new(-3.0, -2.0));
+        assert_eq!(batch.vertices()[11].outline_width, 0.1);
+
+        batch.clear();
+        assert!(batch.is_empty());
+        assert_eq!(batch.shapes(), 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn vertex_layout_matches_attributes() {
+        assert_eq!(check_layout::<ShapeVertex>(), Ok(()));
+        assert_eq!(
+            check_instance_layout::<QuadCorner, RoundRectInstance>(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn instances_keep_the_whole_round_rect() {
+        let rect = RoundRect {
+            center: V2::new(1.0, 2.0),
+            half_extents: V2::new(3.0, 0.5),
+            angle: 0.25,
+            radius: 0.5,
+        };
+        let style = Style::outlined(BLACK, [1.0, 0.0, 0.0, 1.0], 0.2).with_alpha(0.5);
+        let instance = RoundRectInstance::new(&rect, &style);
+        assert_eq!(instance.center, rect.center);
+        assert_eq!(instance.half_extents, rect.half_extents);
+        assert_eq!(instance.angle, 0.25);
+        assert_eq!(instance.radius, 0.5);
+        assert_eq!(instance.outline_width, 0.2);
+        assert_eq!(instance.color, [0.0, 0.0, 0.0, 0.5]);
+        assert_eq!(instance.outline, [1.0, 0.0, 0.0, 0.5]);
+
+        // the shader puts the unit quad's corners where RoundRect::corners does
+        let padding = 0.1;
+        for (corner, (world, local)) in UNIT_QUAD.iter().zip(rect.corners(padding).iter()) {
+            let size = instance.half_extents + V2::repeat(instance.radius + padding);
+            let shader_local = V2::from(corner.corner).component_mul(&size);
+            let shader_world = instance.center + na::Rotation2::new(instance.angle) * shader_local;
+            assert_eq!(shader_local, *local);
+            assert!((shader_world - world).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn pixel_size_follows_zoom() {
+        let projection = na::Orthographic3::new(0.0, 100.0, 50.0, 0.0, -1.0, 1.0).to_homogeneous();
+        let viewport = V2::new(200.0, 100.0);
+        assert_eq!(pixel_size(&projection, viewport), 0.5);
+        let zoomed = projection * na::Matrix4::new_scaling(4.0);
+        assert_eq!(pixel_size(&zoomed, viewport), 0.125);
+        let turned =
+            zoomed * na::Rotation3::from_axis_angle(&na::Vector3::z_axis(), 1.0).to_homogeneous();
+        assert!((pixel_size(&turned, viewport) - 0.125).abs() < 1e-6);
+    }
+}
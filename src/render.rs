@@ -0,0 +1,943 @@
+use crate::level::Visual;
+use crate::world::World;
+
+use rapier2d_f64::geometry::{Segment, Shape, TypedShape};
+use rapier2d_f64::na::Isometry2;
+
+type V2 = na::Vector2<f32>;
+type P2f64 = na::Point2<f64>;
+type V2f64 = na::Vector2<f64>;
+
+/// Red, green, blue and alpha, each from 0.0 to 1.0
+pub type Color = [f32; 4];
+
+pub const BLACK: Color = [0.0, 0.0, 0.0, 1.0];
+pub const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+
+/// How a shape looks. The outline goes around the inside of the shape's edge, so outlining doesn't
+/// change its size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Style {
+    pub fill: Color,
+    pub outline: Color,
+    /// In world units, zero for no outline
+    pub outline_width: f32,
+    /// Multiplies the alpha of both the fill and the outline
+    pub alpha: f32,
+}
+
+impl Style {
+    pub const fn filled(fill: Color) -> Self {
+        Style {
+            fill,
+            outline: BLACK,
+            outline_width: 0.0,
+            alpha: 1.0,
+        }
+    }
+
+    pub const fn outlined(fill: Color, outline: Color, outline_width: f32) -> Self {
+        Style {
+            fill,
+            outline,
+            outline_width,
+            alpha: 1.0,
+        }
+    }
+
+    pub const fn with_alpha(self, alpha: f32) -> Self {
+        Style { alpha, ..self }
+    }
+
+    pub fn fill_color(&self) -> Color {
+        let [r, g, b, a] = self.fill;
+        [r, g, b, a * self.alpha]
+    }
+
+    pub fn outline_color(&self) -> Color {
+        let [r, g, b, a] = self.outline;
+        [r, g, b, a * self.alpha]
+    }
+
+    fn has_outline(&self) -> bool {
+        self.outline_width > 0.0
+    }
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style::filled(BLACK)
+    }
+}
+
+/// The look of each kind of body in the world
+pub fn visual_style(visual: Visual) -> Style {
+    match visual {
+        Visual::Player => Style::outlined([0.95, 0.45, 0.1, 1.0], [0.4, 0.1, 0.0, 1.0], 0.2),
+        Visual::Planet => Style::outlined([0.25, 0.45, 0.8, 1.0], [0.1, 0.2, 0.45, 1.0], 1.0),
+        Visual::Terrain => Style::outlined([0.45, 0.4, 0.35, 1.0], [0.2, 0.18, 0.15, 1.0], 0.5),
+        Visual::Debris => Style::outlined([0.7, 0.65, 0.55, 1.0], [0.35, 0.3, 0.25, 1.0], 0.2),
+    }
+}
+
+/// A rectangle with its corners rounded off by `radius`, which also covers circles (no half
+/// extents) and capsules (one half extent of zero). Backends draw these with smooth edges.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoundRect {
+    pub center: V2,
+    /// Of the rectangle before it's rounded off, so the whole thing is `radius` bigger
+    pub half_extents: V2,
+    /// In radians
+    pub angle: f32,
+    pub radius: f32,
+}
+
+impl RoundRect {
+    pub fn circle(center: V2, radius: f32) -> Self {
+        RoundRect {
+            center,
+            half_extents: V2::zeros(),
+            angle: 0.0,
+            radius,
+        }
+    }
+
+    /// Turns a point in world space into one relative to the center, along the rectangle's sides
+    pub fn to_local(&self, point: V2) -> V2 {
+        na::Rotation2::new(-self.angle) * (point - self.center)
+    }
+
+    /// How far `point` is outside the edge, negative when it's inside
+    pub fn distance(&self, point: V2) -> f32 {
+        let outside = self.to_local(point).abs() - self.half_extents;
+        outside.sup(&V2::zeros()).norm() + outside.x.max(outside.y).min(0.0) - self.radius
+    }
+
+    /// The corners of a rectangle around the whole thing, `padding` away from its edge, in world
+    /// space and relative to the center like `to_local`
+    pub fn corners(&self, padding: f32) -> [(V2, V2); 4] {
+        let size = self.half_extents + V2::new(1.0, 1.0) * (self.radius + padding);
+        let rotation = na::Rotation2::new(self.angle);
+        let corner = |x: f32, y: f32| {
+            let local = V2::new(x * size.x, y * size.y);
+            (self.center + rotation * local, local)
+        };
+        [
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, 1.0),
+        ]
+    }
+}
+
+/// How much of a pixel that's `distance` outside an edge is covered, with `pixel` being its size
+/// in the same units. Edges fade out over a single pixel, however far the camera is zoomed, along
+/// the same curve as GLSL's `smoothstep`.
+pub fn edge_coverage(distance: f32, pixel: f32) -> f32 {
+    if pixel > 0.0 {
+        let t = (0.5 - distance / pixel).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    } else if distance <= 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// The color of a point `distance` outside the edge of a `RoundRect` drawn with `style`, or `None`
+/// if it's too far out to be drawn at all. This is what the GL shader works out for every pixel.
+pub fn round_rect_color(style: &Style, distance: f32, pixel: f32) -> Option<Color> {
+    let coverage = edge_coverage(distance, pixel);
+    if coverage <= 0.0 {
+        return None;
+    }
+    let fill = style.fill_color();
+    let mut color = if style.has_outline() {
+        let outline = style.outline_color();
+        // the outline starts `outline_width` inside the edge
+        let amount = 1.0 - edge_coverage(distance + style.outline_width, pixel);
+        let mut color = fill;
+        for (c, o) in color.iter_mut().zip(outline.iter()) {
+            *c += (o - *c) * amount;
+        }
+        color
+    } else {
+        fill
+    };
+    color[3] *= coverage;
+    Some(color)
+}
+
+/// How much work drawing the last frame took
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DrawStats {
+    pub draw_calls: u32,
+    pub shapes: u32,
+    pub vertices: u32,
+}
+
+/// Something that can draw a frame. Everything is given in world coordinates, `projection` and
+/// `camera` turn them into clip space the same way the GL shaders do.
+pub trait RenderBackend {
+    /// Starts a new frame filled with `clear`
+    fn begin_frame(
+        &mut self,
+        projection: &na::Matrix4<f32>,
+        camera: &na::Matrix4<f32>,
+        clear: Color,
+    );
+    /// Draws the outline of `style` too, unlike the other primitives
+    fn fill_round_rect(&mut self, rect: &RoundRect, style: &Style);
+    /// `points` should go around a convex polygon, in either direction. Its edges aren't smoothed.
+    fn fill_polygon(&mut self, points: &[V2], color: Color);
+    /// Finishes drawing anything still queued up
+    fn end_frame(&mut self) -> DrawStats;
+
+    fn fill_circle(&mut self, center: V2, radius: f32, color: Color) {
+        self.fill_round_rect(&RoundRect::circle(center, radius), &Style::filled(color));
+    }
+
+    fn fill_rect(&mut self, upper_left: V2, lower_right: V2, color: Color) {
+        self.fill_polygon(
+            &[
+                upper_left,
+                V2::new(lower_right.x, upper_left.y),
+                lower_right,
+                V2::new(upper_left.x, lower_right.y),
+            ],
+            color,
+        );
+    }
+
+    /// Draws `shape` placed at `isometry` in the world. For a collider that's its body's position
+    /// combined with the collider's `position_wrt_parent`.
+    fn draw_shape(&mut self, shape: &dyn Shape, isometry: &Isometry2<f64>, style: &Style)
+    where
+        Self: Sized,
+    {
+        paint_shape(self, shape, isometry, style);
+    }
+
+    fn line(&mut self, a: V2, b: V2, width: f32, color: Color) {
+        let side = match (b - a).try_normalize(1.0e-9) {
+            Some(along) => V2::new(-along.y, along.x) * width / 2.0,
+            None => return,
+        };
+        self.fill_polygon(&[a + side, b + side, b - side, a - side], color);
+    }
+
+    /// Draws `text` with the built in pixel font, `size` is the height of a capital letter. Only
+    /// ASCII is supported, lowercase letters are drawn as capitals.
+    fn text(&mut self, top_left: V2, text: &str, size: f32, color: Color) {
+        let pixel = size / GLYPH_HEIGHT as f32;
+        let mut x = top_left.x;
+        for c in text.chars() {
+            let glyph = glyph(c);
+            for (column, bits) in glyph.iter().enumerate() {
+                for row in 0..GLYPH_HEIGHT {
+                    if bits & (1 << row) != 0 {
+                        let corner =
+                            V2::new(x + column as f32 * pixel, top_left.y + row as f32 * pixel);
+                        self.fill_rect(corner, corner + V2::new(pixel, pixel), color);
+                    }
+                }
+            }
+            x += (GLYPH_WIDTH + 1) as f32 * pixel;
+        }
+    }
+}
+
+/// How thick shapes without any area (segments, polylines and heightfields) are drawn
+const LINE_WIDTH: f32 = 0.2;
+/// How many straight pieces each rounded corner is made of
+const CORNER_SEGMENTS: usize = 4;
+/// Sensors don't collide with anything, so they're only hinted at
+const SENSOR_STYLE: Style = Style::filled([0.0, 0.0, 0.0, 0.2]);
+
+/// Draws every collider of every body in `world`, `alpha` of the way between the last two steps
+pub fn draw_world(world: &World, backend: &mut impl RenderBackend, alpha: f64) {
+    for (handle, body) in world.bodies().iter() {
+        let position = world.interpolated_position(handle, alpha);
+        for collider in body.colliders() {
+            let collider = &world.colliders()[*collider];
+            let style = match world.visual(handle) {
+                _ if collider.is_sensor() => SENSOR_STYLE,
+                Some(visual) => visual_style(visual),
+                None => Style::default(),
+            };
+            backend.draw_shape(
+                collider.shape(),
+                &(position * collider.position_wrt_parent()),
+                &style,
+            );
+        }
+    }
+}
+
+/// The outline of a convex polygon grown by `radius` in every direction, so its corners are
+/// rounded
+fn rounded(points: &[P2f64], radius: f64) -> Vec<P2f64> {
+    let n = points.len();
+    // which side the outside is on depends on which way the points go around
+    let area: f64 = (0..n)
+        .map(|i| points[i].coords.perp(&points[(i + 1) % n].coords))
+        .sum();
+    let outward = |a: &P2f64, b: &P2f64| {
+        let along = (b - a).normalize();
+        if area >= 0.0 {
+            V2f64::new(along.y, -along.x)
+        } else {
+            V2f64::new(-along.y, along.x)
+        }
+    };
+    let mut outline = Vec::with_capacity(n * (CORNER_SEGMENTS + 1));
+    for i in 0..n {
+        let (previous, corner, next) = (&points[(i + n - 1) % n], &points[i], &points[(i + 1) % n]);
+        let from = outward(previous, corner);
+        let to = outward(corner, next);
+        let start = from.y.atan2(from.x);
+        let sweep = from.perp(&to).atan2(from.dot(&to));
+        for step in 0..=CORNER_SEGMENTS {
+            let angle = start + sweep * step as f64 / CORNER_SEGMENTS as f64;
+            outline.push(corner + V2f64::new(angle.cos(), angle.sin()) * radius);
+        }
+    }
+    outline
+}
+
+/// `points` moved `width` towards the inside of the convex polygon they go around, or `None` if
+/// the polygon is too thin for that
+fn inset(points: &[V2], width: f32) -> Option<Vec<V2>> {
+    let n = points.len();
+    let area: f32 = (0..n).map(|i| points[i].perp(&points[(i + 1) % n])).sum();
+    let inward = |a: &V2, b: &V2| {
+        let along = (b - a).try_normalize(1.0e-9)?;
+        Some(if area >= 0.0 {
+            V2::new(-along.y, along.x)
+        } else {
+            V2::new(along.y, -along.x)
+        })
+    };
+    let mut inset = Vec::with_capacity(n);
+    for i in 0..n {
+        let (previous, corner, next) = (&points[(i + n - 1) % n], &points[i], &points[(i + 1) % n]);
+        let (from, to) = (inward(previous, corner)?, inward(corner, next)?);
+        // the corner moves along the bisector, far enough that both edges move by `width`
+        let bisector = from + to;
+        let scale = 1.0 + from.dot(&to);
+        if scale < 1.0e-3 {
+            return None;
+        }
+        inset.push(corner + bisector * width / scale);
+    }
+    // an edge turning around means the opposite sides went past each other
+    let flipped = (0..n).any(|i| {
+        let j = (i + 1) % n;
+        (inset[j] - inset[i]).dot(&(points[j] - points[i])) <= 0.0
+    });
+    if flipped {
+        None
+    } else {
+        Some(inset)
+    }
+}
+
+/// Fills a convex polygon with `style`'s outline around the inside of its edge
+fn fill_outlined(backend: &mut impl RenderBackend, points: &[V2], style: &Style) {
+    let mut points = points.to_vec();
+    points.dedup_by(|a, b| (*a - *b).norm_squared() < 1.0e-12);
+    if !style.has_outline() || points.len() < 3 {
+        backend.fill_polygon(&points, style.fill_color());
+        return;
+    }
+    let inner = match inset(&points, style.outline_width) {
+        Some(inner) => inner,
+        None => return backend.fill_polygon(&points, style.outline_color()),
+    };
+    let n = points.len();
+    for i in 0..n {
+        let j = (i + 1) % n;
+        backend.fill_polygon(
+            &[points[i], points[j], inner[j], inner[i]],
+            style.outline_color(),
+        );
+    }
+    backend.fill_polygon(&inner, style.fill_color());
+}
+
+fn paint_shape(
+    backend: &mut impl RenderBackend,
+    shape: &dyn Shape,
+    position: &Isometry2<f64>,
+    style: &Style,
+) {
+    let to_world = |p: &P2f64| -> V2 { na::convert((position * p).coords) };
+    let polygon = |backend: &mut _, points: &[P2f64]| {
+        let points: Vec<V2> = points.iter().map(to_world).collect();
+        fill_outlined(backend, &points, style);
+    };
+    let round_rect =
+        |backend: &mut _, center: &P2f64, half_extents: V2, angle: f64, radius: f64| {
+            let rect = RoundRect {
+                center: to_world(center),
+                half_extents,
+                angle: (position.rotation.angle() + angle) as f32,
+                radius: radius as f32,
+            };
+            RenderBackend::fill_round_rect(backend, &rect, style);
+        };
+    // shapes without an inside (lines and the triangles of a mesh) aren't outlined
+    let color = style.fill_color();
+    let segment = |backend: &mut _, s: &Segment| {
+        RenderBackend::line(backend, to_world(&s.a), to_world(&s.b), LINE_WIDTH, color);
+    };
+    match shape.as_typed_shape() {
+        TypedShape::Ball(ball) => {
+            round_rect(backend, &P2f64::origin(), V2::zeros(), 0.0, ball.radius)
+        }
+        TypedShape::Cuboid(cuboid) => round_rect(
+            backend,
+            &P2f64::origin(),
+            na::convert(cuboid.half_extents),
+            0.0,
+            0.0,
+        ),
+        TypedShape::RoundCuboid(cuboid) => round_rect(
+            backend,
+            &P2f64::origin(),
+            na::convert(cuboid.base_shape.half_extents),
+            0.0,
+            cuboid.border_radius,
+        ),
+        TypedShape::Capsule(capsule) => {
+            let along = capsule.segment.b - capsule.segment.a;
+            round_rect(
+                backend,
+                &na::center(&capsule.segment.a, &capsule.segment.b),
+                V2::new(along.norm() as f32 / 2.0, 0.0),
+                along.y.atan2(along.x),
+                capsule.radius,
+            )
+        }
+        TypedShape::Segment(s) => segment(backend, s),
+        TypedShape::Triangle(t) => polygon(backend, &[t.a, t.b, t.c]),
+        TypedShape::RoundTriangle(round) => {
+            let t = &round.base_shape;
+            polygon(backend, &rounded(&[t.a, t.b, t.c], round.border_radius))
+        }
+        TypedShape::ConvexPolygon(p) => polygon(backend, p.points()),
+        TypedShape::RoundConvexPolygon(p) => {
+            polygon(backend, &rounded(p.base_shape.points(), p.border_radius))
+        }
+        TypedShape::Polyline(polyline) => {
+            for s in polyline.segments() {
+                segment(backend, &s);
+            }
+        }
+        TypedShape::TriMesh(mesh) => {
+            for t in mesh.triangles() {
+                backend.fill_polygon(&[to_world(&t.a), to_world(&t.b), to_world(&t.c)], color);
+            }
+        }
+        TypedShape::HeightField(heightfield) => {
+            for s in heightfield.segments() {
+                segment(backend, &s);
+            }
+        }
+        TypedShape::Compound(compound) => {
+            for (part_position, part) in compound.shapes() {
+                paint_shape(backend, &*part.0, &(position * part_position), style);
+            }
+        }
+        // half spaces go on forever, and there's no telling what custom shapes look like
+        TypedShape::HalfSpace(_) | TypedShape::Custom(_) => (),
+    }
+}
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+/// A column per byte, top row in the lowest bit, from `' '` to `'_'`
+const FONT: [[u8; GLYPH_WIDTH]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x56, 0x20, 0x50], // &
+    [0x00, 0x00, 0x07, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x2a, 0x1c, 0x7f, 0x1c, 0x2a], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x72, 0x49, 0x49, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x49, 0x4d, 0x33], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x31], // 6
+    [0x41, 0x21, 0x11, 0x09, 0x07], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x46, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x00, 0x14, 0x00, 0x00], // :
+    [0x00, 0x40, 0x34, 0x00, 0x00], // ;
+    [0x00, 0x08, 0x14, 0x22, 0x41], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x41, 0x22, 0x14, 0x08, 0x00], // >
+    [0x02, 0x01, 0x59, 0x09, 0x06], // ?
+    [0x3e, 0x41, 0x5d, 0x59, 0x4e], // @
+    [0x7c, 0x12, 0x11, 0x12, 0x7c], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x41, 0x3e], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3e, 0x41, 0x41, 0x51, 0x73], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x1c, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x26, 0x49, 0x49, 0x49, 0x32], // S
+    [0x03, 0x01, 0x7f, 0x01, 0x03], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x03, 0x04, 0x78, 0x04, 0x03], // Y
+    [0x61, 0x59, 0x49, 0x4d, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+];
+
+fn glyph(c: char) -> &'static [u8; GLYPH_WIDTH] {
+    let c = c.to_ascii_uppercase();
+    match c {
+        ' '..='_' => &FONT[c as usize - ' ' as usize],
+        _ => &FONT['?' as usize - ' ' as usize],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rapier2d_f64::geometry::{Compound, Cuboid, SharedShape};
+
+    /// The corners of a cuboid, going around it
+    fn cuboid_points(half_extents: &V2f64) -> Vec<P2f64> {
+        vec![
+            P2f64::new(-half_extents.x, -half_extents.y),
+            P2f64::new(half_extents.x, -half_extents.y),
+            P2f64::new(half_extents.x, half_extents.y),
+            P2f64::new(-half_extents.x, half_extents.y),
+        ]
+    }
+
+    /// Just remembers what it was asked to draw
+    #[derive(Default)]
+    struct Recorder {
+        rects: Vec<(RoundRect, Style)>,
+        polygons: Vec<Vec<V2>>,
+        /// Of the polygons
+        colors: Vec<Color>,
+    }
+
+    impl RenderBackend for Recorder {
+        fn begin_frame(&mut self, _: &na::Matrix4<f32>, _: &na::Matrix4<f32>, _: Color) {}
+        fn fill_round_rect(&mut self, rect: &RoundRect, style: &Style) {
+            self.rects.push((*rect, *style));
+        }
+        fn fill_polygon(&mut self, points: &[V2], color: Color) {
+            self.polygons.push(points.to_vec());
+            self.colors.push(color);
+        }
+        fn end_frame(&mut self) -> DrawStats {
+            DrawStats::default()
+        }
+    }
+
+    #[test]
+    fn provided_shapes_are_polygons() {
+        let mut recorder = Recorder::default();
+        recorder.fill_rect(V2::new(0.0, 0.0), V2::new(2.0, 1.0), BLACK);
+        assert_eq!(
+            recorder.polygons[0],
+            vec![
+                V2::new(0.0, 0.0),
+                V2::new(2.0, 0.0),
+                V2::new(2.0, 1.0),
+                V2::new(0.0, 1.0)
+            ]
+        );
+
+        recorder.line(V2::new(0.0, 0.0), V2::new(0.0, 10.0), 2.0, BLACK);
+        let xs: Vec<f32> = recorder.polygons[1].iter().map(|p| p.x).collect();
+        assert_eq!(xs, vec![-1.0, -1.0, 1.0, 1.0]);
+        // lines with no length are skipped
+        recorder.line(V2::new(3.0, 3.0), V2::new(3.0, 3.0), 2.0, BLACK);
+        assert_eq!(recorder.polygons.len(), 2);
+
+        recorder.polygons.clear();
+        recorder.text(V2::new(0.0, 0.0), "-", 7.0, BLACK);
+        assert_eq!(recorder.polygons.len(), 5);
+        assert!(recorder.polygons.iter().all(|p| p[0].y == 3.0));
+    }
+
+    #[test]
+    fn draws_world_bodies() {
+        let world = World::new(Default::default());
+        let mut recorder = Recorder::default();
+        draw_world(&world, &mut recorder, 1.0);
+        // player and planet, then the two boulders
+        assert_eq!(
+            recorder.rects[..2],
+            [
+                (
+                    RoundRect::circle(V2::new(0.0, 0.0), 1.0),
+                    visual_style(Visual::Player)
+                ),
+                (
+                    RoundRect::circle(V2::new(0.0, 140.0), 50.0),
+                    visual_style(Visual::Planet)
+                ),
+            ]
+        );
+        let (boulder, style) = recorder.rects[2];
+        assert_eq!(boulder.center, V2::new(-30.0, 100.0));
+        assert_eq!(boulder.half_extents, V2::new(4.0, 8.0));
+        assert_eq!(style, visual_style(Visual::Terrain));
+        assert_eq!(recorder.rects.len(), 4);
+        assert!(recorder.polygons.is_empty());
+        assert_ne!(
+            visual_style(Visual::Planet).fill,
+            visual_style(Visual::Terrain).fill
+        );
+    }
+
+    #[test]
+    fn benchmark_bodies_are_round_rects() {
+        // which instancing backends draw all in one go
+        let level = crate::level::Level::benchmark(1000);
+        let world = World::from_level(Default::default(), &level).unwrap();
+        let mut recorder = Recorder::default();
+        draw_world(&world, &mut recorder, 1.0);
+        // and the player and planet
+        assert_eq!(recorder.rects.len(), 1002);
+        assert!(recorder.polygons.is_empty());
+    }
+
+    #[test]
+    fn round_rect_distances() {
+        let circle = RoundRect::circle(V2::new(1.0, 1.0), 2.0);
+        assert_eq!(circle.distance(V2::new(1.0, 1.0)), -2.0);
+        assert_eq!(circle.distance(V2::new(1.0, 5.0)), 2.0);
+
+        // a capsule lying along the y axis
+        let capsule = RoundRect {
+            center: V2::zeros(),
+            half_extents: V2::new(3.0, 0.0),
+            angle: std::f32::consts::FRAC_PI_2,
+            radius: 1.0,
+        };
+        assert!(capsule.distance(V2::new(0.0, 4.0)).abs() < 1e-6);
+        assert!((capsule.distance(V2::new(0.0, 5.0)) - 1.0).abs() < 1e-6);
+        assert!((capsule.distance(V2::new(2.0, 0.0)) - 1.0).abs() < 1e-6);
+        assert!(capsule.corners(0.5)[0].0.x.abs() < 1.5 + 1e-6);
+
+        let square = RoundRect {
+            center: V2::zeros(),
+            half_extents: V2::new(1.0, 1.0),
+            angle: 0.0,
+            radius: 0.0,
+        };
+        assert_eq!(square.distance(V2::new(0.5, 0.0)), -0.5);
+        assert_eq!(square.distance(V2::new(4.0, 5.0)), 5.0);
+    }
+
+    #[test]
+    fn edges_are_smooth() {
+        // half covered right on the edge, whatever the zoom
+        assert_eq!(edge_coverage(0.0, 1.0), 0.5);
+        assert_eq!(edge_coverage(0.0, 0.01), 0.5);
+        assert_eq!(edge_coverage(-0.25, 1.0), 0.84375);
+        assert_eq!(edge_coverage(0.25, 1.0), 0.15625);
+        assert_eq!(edge_coverage(-0.25, 0.01), 1.0);
+        assert_eq!(edge_coverage(0.5, 1.0), 0.0);
+        // without any pixels, edges are hard
+        assert_eq!(edge_coverage(-0.1, 0.0), 1.0);
+        assert_eq!(edge_coverage(0.1, 0.0), 0.0);
+
+        let style = Style::outlined(WHITE, BLACK, 1.0);
+        assert_eq!(round_rect_color(&style, 1.0, 0.1), None);
+        assert_eq!(round_rect_color(&style, -0.5, 0.1), Some(BLACK));
+        assert_eq!(round_rect_color(&style, -2.0, 0.1), Some(WHITE));
+        // halfway between the outline and the fill, and half covered at the edge
+        assert_eq!(
+            round_rect_color(&style, -1.0, 0.1),
+            Some([0.5, 0.5, 0.5, 1.0])
+        );
+        assert_eq!(
+            round_rect_color(&style.with_alpha(0.5), 0.0, 0.1),
+            Some([0.0, 0.0, 0.0, 0.25])
+        );
+    }
+
+    #[test]
+    fn outlines_go_inside() {
+        let square = [
+            V2::new(0.0, 0.0),
+            V2::new(4.0, 0.0),
+            V2::new(4.0, 4.0),
+            V2::new(0.0, 4.0),
+        ];
+        let style = Style::outlined(WHITE, BLACK, 1.0).with_alpha(0.5);
+        let mut recorder = Recorder::default();
+        fill_outlined(&mut recorder, &square, &style);
+        assert_eq!(recorder.polygons.len(), 5);
+        assert_eq!(
+            recorder.polygons[4],
+            vec![
+                V2::new(1.0, 1.0),
+                V2::new(3.0, 1.0),
+                V2::new(3.0, 3.0),
+                V2::new(1.0, 3.0)
+            ]
+        );
+        assert_eq!(recorder.colors[0], [0.0, 0.0, 0.0, 0.5]);
+        assert_eq!(recorder.colors[4], [1.0, 1.0, 1.0, 0.5]);
+
+        // the other way around works just as well
+        let mut reversed = square;
+        reversed.reverse();
+        let mut recorder = Recorder::default();
+        fill_outlined(&mut recorder, &reversed, &style);
+        assert!(recorder.polygons[4].contains(&V2::new(3.0, 3.0)));
+
+        // too thin to fit the outline on both sides, so it's all outline
+        let mut recorder = Recorder::default();
+        fill_outlined(&mut recorder, &square, &Style::outlined(WHITE, BLACK, 2.5));
+        assert_eq!(recorder.polygons, vec![square.to_vec()]);
+        assert_eq!(recorder.colors, vec![BLACK]);
+    }
+
+    fn close(a: V2, b: V2) -> bool {
+        (a - b).norm() < 1e-4
+    }
+
+    #[test]
+    fn draws_every_shape() {
+        let p = |x, y| P2f64::new(x, y);
+        let shapes = vec![
+            (SharedShape::ball(1.0), 1, 0),
+            (SharedShape::cuboid(1.0, 2.0), 1, 0),
+            (SharedShape::round_cuboid(1.0, 2.0, 0.5), 1, 0),
+            (SharedShape::capsule(p(0.0, -1.0), p(0.0, 1.0), 0.5), 1, 0),
+            (SharedShape::segment(p(0.0, 0.0), p(1.0, 0.0)), 0, 1),
+            (
+                SharedShape::triangle(p(0.0, 0.0), p(1.0, 0.0), p(0.0, 1.0)),
+                0,
+                1,
+            ),
+            (
+                SharedShape::round_triangle(p(0.0, 0.0), p(1.0, 0.0), p(0.0, 1.0), 0.1),
+                0,
+                1,
+            ),
+            (
+                SharedShape::convex_hull(&[p(0.0, 0.0), p(2.0, 0.0), p(1.0, 1.0)]).unwrap(),
+                0,
+                1,
+            ),
+            (
+                SharedShape::round_convex_hull(&[p(0.0, 0.0), p(2.0, 0.0), p(1.0, 1.0)], 0.1)
+                    .unwrap(),
+                0,
+                1,
+            ),
+            (
+                SharedShape::polyline(vec![p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0)], None),
+                0,
+                2,
+            ),
+            (
+                SharedShape::trimesh(
+                    vec![p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)],
+                    vec![[0, 1, 2], [0, 2, 3]],
+                ),
+                0,
+                2,
+            ),
+            (
+                SharedShape::heightfield(
+                    na::DVector::from_column_slice(&[0.0, 1.0, 0.0]),
+                    V2f64::new(4.0, 1.0),
+                ),
+                0,
+                2,
+            ),
+            (
+                SharedShape::compound(vec![
+                    (Isometry2::translation(1.0, 0.0), SharedShape::ball(0.5)),
+                    (
+                        Isometry2::translation(-1.0, 0.0),
+                        SharedShape::cuboid(0.5, 0.5),
+                    ),
+                ]),
+                2,
+                0,
+            ),
+        ];
+        for (shape, rects, polygons) in shapes {
+            let mut recorder = Recorder::default();
+            recorder.draw_shape(&*shape.0, &Isometry2::identity(), &Style::default());
+            let counts = (recorder.rects.len(), recorder.polygons.len());
+            assert_eq!(counts, (rects, polygons), "{:?}", shape.shape_type());
+        }
+    }
+
+    #[test]
+    fn shapes_are_placed_with_their_isometry() {
+        let mut recorder = Recorder::default();
+        let position = Isometry2::new(V2f64::new(10.0, 0.0), std::f64::consts::FRAC_PI_2);
+        recorder.draw_shape(
+            &Cuboid::new(V2f64::new(2.0, 1.0)),
+            &position,
+            &Style::default(),
+        );
+        // a quarter turn takes (x, y) to (-y, x)
+        let corners = recorder.rects[0].0.corners(0.0);
+        assert!(close(corners[0].0, V2::new(11.0, -2.0)));
+        assert!(close(corners[2].0, V2::new(9.0, 2.0)));
+
+        let compound = Compound::new(vec![(
+            Isometry2::translation(1.0, 0.0),
+            SharedShape::ball(0.5),
+        )]);
+        recorder.draw_shape(&compound, &position, &Style::default());
+        assert!(close(recorder.rects[1].0.center, V2::new(10.0, 1.0)));
+    }
+
+    #[test]
+    fn rounded_corners_stay_round() {
+        let outline = rounded(&cuboid_points(&V2f64::new(1.0, 1.0)), 0.5);
+        assert_eq!(outline.len(), 4 * (CORNER_SEGMENTS + 1));
+        // every point is exactly the radius away from the square
+        for point in outline.iter() {
+            let outside = point.coords.abs() - V2f64::new(1.0, 1.0);
+            let distance = outside.sup(&V2f64::zeros()).norm();
+            assert!((distance - 0.5).abs() < 1e-9, "{}", point);
+        }
+        // no matter which way around the corners go
+        let mut reversed = cuboid_points(&V2f64::new(1.0, 1.0));
+        reversed.reverse();
+        assert!(rounded(&reversed, 0.5)
+            .iter()
+            .all(|p| p.coords.abs().max() >= 1.0));
+    }
+
+    #[test]
+    fn matches_rapier_aabbs() {
+        use rapier2d_f64::dynamics::{
+            CCDSolver, IntegrationParameters, JointSet, RigidBodyBuilder, RigidBodySet,
+        };
+        use rapier2d_f64::geometry::{BroadPhase, ColliderBuilder, ColliderSet, NarrowPhase};
+        use rapier2d_f64::pipeline::PhysicsPipeline;
+
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+        let body = bodies.insert(
+            RigidBodyBuilder::new_dynamic()
+                .position(Isometry2::new(V2f64::new(3.0, 4.0), 0.7))
+                .angvel(2.0)
+                .linvel(1.0, -0.5)
+                .build(),
+        );
+        let offset = |x, y, angle| Isometry2::new(V2f64::new(x, y), angle);
+        for collider in [
+            ColliderBuilder::cuboid(2.0, 0.5).position_wrt_parent(offset(1.0, -2.0, 0.3)),
+            ColliderBuilder::triangle(
+                P2f64::new(0.0, 0.0),
+                P2f64::new(2.0, 0.0),
+                P2f64::new(0.0, 1.0),
+            )
+            .position_wrt_parent(offset(-3.0, 0.0, -1.0)),
+            ColliderBuilder::ball(0.75).position_wrt_parent(offset(0.0, 2.0, 0.0)),
+        ] {
+            colliders.insert(collider.build(), body, &mut bodies);
+        }
+
+        // spin it around for a bit
+        let mut pipeline = PhysicsPipeline::new();
+        let mut broad_phase = BroadPhase::new();
+        let mut narrow_phase = NarrowPhase::new();
+        let mut joints = JointSet::new();
+        let mut ccd_solver = CCDSolver::new();
+        for _ in 0..37 {
+            pipeline.step(
+                &V2f64::zeros(),
+                &IntegrationParameters::default(),
+                &mut broad_phase,
+                &mut narrow_phase,
+                &mut bodies,
+                &mut colliders,
+                &mut joints,
+                &mut ccd_solver,
+                &(),
+                &(),
+            );
+        }
+
+        for (_, collider) in colliders.iter() {
+            let mut recorder = Recorder::default();
+            let isometry = bodies[collider.parent()].position() * collider.position_wrt_parent();
+            recorder.draw_shape(collider.shape(), &isometry, &Style::default());
+
+            let mut points: Vec<V2> = recorder.polygons.concat();
+            for (rect, _) in recorder.rects.iter() {
+                // the rounded off corners are as far out as a circle around each corner
+                let radius = V2::new(rect.radius, rect.radius);
+                let corners = RoundRect {
+                    radius: 0.0,
+                    ..*rect
+                }
+                .corners(0.0);
+                for (corner, _) in corners.iter() {
+                    points.push(corner - radius);
+                    points.push(corner + radius);
+                }
+            }
+            let mins = points.iter().fold(points[0], |m, p| m.inf(p));
+            let maxs = points.iter().fold(points[0], |m, p| m.sup(p));
+
+            let aabb = collider.compute_aabb();
+            assert!(
+                close(mins, na::convert(aabb.mins.coords)),
+                "{} {}",
+                mins,
+                aabb.mins
+            );
+            assert!(
+                close(maxs, na::convert(aabb.maxs.coords)),
+                "{} {}",
+                maxs,
+                aabb.maxs
+            );
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use crate::config::{Config, PhysicsConfig};
+use crate::level::Level;
+use crate::player_controller::ControllerConfig;
+use crate::world::{Input, World, WorldConfig};
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// Everything needed to play a session back exactly: how the world was created and the input
+/// given to every single physics step
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    /// The settings the world was created with, after the level's overrides. Replays use these
+    /// rather than whatever config there is when they're played back.
+    pub physics: PhysicsConfig,
+    pub player: ControllerConfig,
+    /// The whole level that was played, so changing or losing its file doesn't change the replay
+    pub level: Level,
+    inputs: Vec<Input>,
+}
+
+impl Recording {
+    const MAGIC: &'static [u8; 8] = b"PLNTREC\0";
+    const VERSION: u8 = 5;
+    /// The most steps a recording read from a file can have. At the default 60 steps a second
+    /// that's over 46 hours of play, so anything longer is a broken file, which shouldn't get to
+    /// use up all the memory.
+    const MAX_STEPS: usize = 10_000_000;
+
+    /// For playing `level` with `config`, which should already have the level's overrides
+    pub fn new(config: &Config, level: Level) -> Self {
+        Recording {
+            physics: config.physics.clone(),
+            player: config.player.clone(),
+            level,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Should be called with exactly what was passed to `World::step`, every step
+    pub fn record(&mut self, input: &Input) {
+        self.inputs.push(*input);
+    }
+
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    /// What the world was created with
+    pub fn world_config(&self) -> WorldConfig {
+        Config {
+            physics: self.physics.clone(),
+            player: self.player.clone(),
+            ..Config::default()
+        }
+        .world_config()
+    }
+
+    /// Creates the world this recording started from
+    pub fn start(&self) -> Result<World, String> {
+        World::from_level(self.world_config(), &self.level)
+    }
+
+    /// Creates the world this recording started from and runs every recorded step on it
+    pub fn replay(&self) -> Result<World, String> {
+        let mut world = self.start()?;
+        for input in self.inputs.iter() {
+            world.step(input);
+        }
+        Ok(world)
+    }
+
+    /// Writes the recording in a small binary format. Runs of identical input (which is most of
+    /// them) are only stored once, along with how many steps they lasted.
+    pub fn write(&self, mut w: impl Write) -> std::io::Result<()> {
+        w.write_all(Self::MAGIC)?;
+        w.write_all(&[Self::VERSION])?;
+        // json, which gives back exactly the same floats
+        let config = serde_json::to_vec(&(&self.physics, &self.player))?;
+        w.write_all(&(config.len() as u32).to_le_bytes())?;
+        w.write_all(&config)?;
+        let level = serde_json::to_vec(&self.level)?;
+        w.write_all(&(level.len() as u32).to_le_bytes())?;
+        w.write_all(&level)?;
+
+        let mut runs: Vec<(u32, &Input)> = Vec::new();
+        for input in self.inputs.iter() {
+            match runs.last_mut() {
+                Some((count, last)) if *last == input && *count < u32::MAX => *count += 1,
+                _ => runs.push((1, input)),
+            }
+        }
+        w.write_all(&(runs.len() as u32).to_le_bytes())?;
+        for (count, input) in runs.iter() {
+            w.write_all(&count.to_le_bytes())?;
+            w.write_all(&input.horizontal_movement.to_bits().to_le_bytes())?;
+            w.write_all(&[input.jump as u8 | (input.jump_held as u8) << 1])?;
+        }
+        Ok(())
+    }
+
+    pub fn read(mut r: impl Read) -> Result<Self, String> {
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes)
+            .map_err(|e| format!("Failed to read recording: {}", e))?;
+        let mut bytes = &bytes[..];
+        let mut take = |len: usize| -> Result<&[u8], String> {
+            if bytes.len() < len {
+                return Err("Recording is cut off".to_string());
+            }
+            let (taken, rest) = bytes.split_at(len);
+            bytes = rest;
+            Ok(taken)
+        };
+
+        if take(Self::MAGIC.len())? != Self::MAGIC {
+            return Err("Not a recording".to_string());
+        }
+        let version = take(1)?[0];
+        if version != Self::VERSION {
+            return Err(format!(
+                "Recording is version {}, but only version {} can be read",
+                version,
+                Self::VERSION
+            ));
+        }
+        let config_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let (physics, player) = serde_json::from_slice(take(config_len)?)
+            .map_err(|e| format!("Recording's config is invalid: {}", e))?;
+        let level_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+        let level = std::str::from_utf8(take(level_len)?)
+            .map_err(|e| e.to_string())
+            .and_then(Level::parse)
+            .map_err(|e| format!("Recording's level is invalid: {}", e))?;
+
+        let run_count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let mut inputs = Vec::new();
+        for _ in 0..run_count {
+            let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+            let horizontal_movement =
+                f64::from_bits(u64::from_le_bytes(take(8)?.try_into().unwrap()));
+            let flags = take(1)?[0];
+            let input = Input {
+                horizontal_movement,
+                jump: flags & 1 != 0,
+                jump_held: flags & 2 != 0,
+            };
+            if count as usize > Self::MAX_STEPS - inputs.len() {
+                return Err(format!(
+                    "Recording is more than {} steps long",
+                    Self::MAX_STEPS
+                ));
+            }
+            inputs.resize(inputs.len() + count as usize, input);
+        }
+        Ok(Recording {
+            physics,
+            player,
+            level,
+            inputs,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        std::fs::File::create(path)
+            .and_then(|f| self.write(std::io::BufWriter::new(f)))
+            .map_err(|e| format!("Failed to write recording {}: {}", path.display(), e))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("Failed to open recording {}: {}", path.display(), e))?;
+        Self::read(file).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walks back and forth and jumps around a bit
+    fn scripted_session() -> (Recording, World) {
+        let mut recording = Recording::new(&Config::default(), Level::builtin());
+        let mut world = World::new(WorldConfig::default());
+        for i in 0..1200 {
+            let input = Input {
+                horizontal_movement: match (i / 150) % 4 {
+                    0 => 1.0,
+                    2 => -0.5,
+                    _ => 0.0,
+                },
+                jump: i % 200 == 100,
+                jump_held: i % 200 >= 100 && i % 200 < 110,
+            };
+            recording.record(&input);
+            world.step(&input);
+        }
+        (recording, world)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (recording, _) = scripted_session();
+        let mut bytes = Vec::new();
+        recording.write(&mut bytes).unwrap();
+        let mut empty = Vec::new();
+        Recording::new(&Config::default(), Level::builtin())
+            .write(&mut empty)
+            .unwrap();
+        // run length encoding keeps the inputs much smaller than one entry per step
+        assert!(bytes.len() - empty.len() < recording.inputs().len());
+        assert_eq!(Recording::read(&bytes[..]).unwrap(), recording);
+    }
+
+    #[test]
+    fn replay_is_exact() {
+        let (recording, live) = scripted_session();
+        let replayed = recording.replay().unwrap();
+        assert_eq!(replayed.state_hash(), live.state_hash());
+        assert_eq!(replayed.player().position(), live.player().position());
+    }
+
+    #[test]
+    fn benchmarks_replay_on_the_same_level() {
+        let mut recording = Recording::new(&Config::default(), Level::benchmark(50));
+        let mut live = World::from_level(WorldConfig::default(), &Level::benchmark(50)).unwrap();
+        for _ in 0..30 {
+            recording.record(&Input::default());
+            live.step(&Input::default());
+        }
+        let mut bytes = Vec::new();
+        recording.write(&mut bytes).unwrap();
+        let read = Recording::read(&bytes[..]).unwrap();
+        assert_eq!(read.level, Level::benchmark(50));
+        let replayed = read.replay().unwrap();
+        assert_eq!(replayed.state_hash(), live.state_hash());
+    }
+
+    #[test]
+    fn replays_with_the_recorded_config() {
+        let mut config = Config::default();
+        config.physics.steps_per_second = 120.0;
+        config.physics.gravity_scale = 1.5;
+        config.player.jump_impulse = 150.0;
+        let mut recording = Recording::new(&config, Level::builtin());
+        let mut live = World::new(config.world_config());
+        for i in 0..300 {
+            let input = Input {
+                horizontal_movement: 1.0,
+                jump: i == 100,
+                jump_held: i >= 100,
+            };
+            recording.record(&input);
+            live.step(&input);
+        }
+        let mut bytes = Vec::new();
+        recording.write(&mut bytes).unwrap();
+        let read = Recording::read(&bytes[..]).unwrap();
+        assert_eq!(read, recording);
+        assert_eq!(read.replay().unwrap().state_hash(), live.state_hash());
+    }
+
+    /// If this fails, something changed how the simulation behaves. If that was on purpose, update
+    /// the hash.
+    #[test]
+    fn golden_hash() {
+        let (recording, _) = scripted_session();
+        let world = recording.replay().unwrap();
+        assert_eq!(
+            world.state_hash(),
+            0x27b5_07bd_5b79_0fa9,
+            "{:#x}",
+            world.state_hash()
+        );
+    }
+
+    #[test]
+    fn state_hash_notices_differences() {
+        let (mut recording, live) = scripted_session();
+        recording.record(&Input::default());
+        assert_ne!(recording.replay().unwrap().state_hash(), live.state_hash());
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(Recording::read(&b"not a recording at all"[..]).is_err());
+
+        let (recording, _) = scripted_session();
+        let mut bytes = Vec::new();
+        recording.write(&mut bytes).unwrap();
+        let err = Recording::read(&bytes[..bytes.len() - 3]).unwrap_err();
+        assert!(err.contains("cut off"), "{}", err);
+        bytes[8] = 200;
+        let err = Recording::read(&bytes[..]).unwrap_err();
+        assert!(err.contains("version 200"), "{}", err);
+
+        // two runs that would take up gigabytes
+        let mut bytes = Vec::new();
+        Recording::new(&Config::default(), Level::builtin())
+            .write(&mut bytes)
+            .unwrap();
+        bytes.truncate(bytes.len() - 4);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        for _ in 0..2 {
+            bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+            bytes.extend_from_slice(&[0; 9]);
+        }
+        let err = Recording::read(&bytes[..]).unwrap_err();
+        assert!(err.contains("steps long"), "{}", err);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::camera::Camera2D;
+use crate::render::{draw_world, RenderBackend, WHITE};
+use crate::replay::Recording;
+use crate::software_render::SoftwareRenderer;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+/// Encodes 8 bit RGBA pixels, rows from the top of the image down, as a PNG file. PNGs can't be
+/// empty, so fails if `width` or `height` is 0, like a minimized window's.
+pub fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Result<Vec<u8>, String> {
+    assert_eq!(rgba.len(), width * height * 4, "wrong amount of pixels");
+    if width == 0 || height == 0 {
+        return Err(format!("Can't save an empty {}x{} image", width, height));
+    }
+    let mut png = PNG_SIGNATURE.to_vec();
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth, color type (RGBA), compression, filter and interlace method
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in rgba.chunks(width * 4) {
+        // every row starts with which filter it uses, none is good enough for screenshots
+        encoder.write_all(&[0]).unwrap();
+        encoder.write_all(row).unwrap();
+    }
+    write_chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+
+    write_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+pub fn save_png(
+    path: impl AsRef<Path>,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+) -> Result<(), String> {
+    let path = path.as_ref();
+    std::fs::write(path, encode_png(width, height, rgba)?)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// GL reads pixels starting from the bottom row, this puts them the other way around
+pub fn flip_rows(rgba: &mut [u8], width: usize) {
+    let row_len = width * 4;
+    if row_len == 0 {
+        return;
+    }
+    let rows = rgba.len() / row_len;
+    for y in 0..rows / 2 {
+        let (top, bottom) = rgba.split_at_mut((rows - 1 - y) * row_len);
+        top[y * row_len..(y + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+    }
+}
+
+/// Where frame `index` of a sequence goes, numbered so they sort in order
+pub fn frame_path(dir: impl AsRef<Path>, index: usize) -> PathBuf {
+    dir.as_ref().join(format!("frame_{:05}.png", index))
+}
+
+/// Plays `recording` back headless and renders the first `frames` steps (or all of them) to
+/// numbered PNGs in `dir`, using the software renderer. `camera` follows the player the same way
+/// it does in game. Returns how many were written.
+pub fn render_replay_frames(
+    recording: &Recording,
+    dir: impl AsRef<Path>,
+    frames: Option<usize>,
+    renderer: &mut SoftwareRenderer,
+    camera: &mut Camera2D,
+) -> Result<usize, String> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let viewport = na::Vector2::new(renderer.width() as f32, renderer.height() as f32);
+    let projection =
+        na::Orthographic3::new(0.0, viewport.x, viewport.y, 0.0, -1.0, 1.0).to_homogeneous();
+    let dt = recording.world_config().integration_parameters.dt as f32;
+
+    let mut written = 0;
+    let mut save_frame = |world: &_, renderer: &mut SoftwareRenderer| {
+        camera.update(world, 1.0, dt);
+        renderer.begin_frame(&projection, &camera.matrix(viewport), WHITE);
+        draw_world(world, renderer, 1.0);
+        renderer.end_frame();
+        let path = frame_path(dir, written);
+        written += 1;
+        save_png(path, renderer.width(), renderer.height(), renderer.pixels())
+    };
+
+    let mut world = recording.start()?;
+    let inputs = recording.inputs();
+    let count = frames.unwrap_or(inputs.len() + 1).min(inputs.len() + 1);
+    if count > 0 {
+        save_frame(&world, renderer)?;
+    }
+    for input in inputs.iter().take(count.saturating_sub(1)) {
+        world.step(input);
+        save_frame(&world, renderer)?;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::level::Level;
+    use crate::world::Input;
+    use flate2::read::ZlibDecoder;
+    use std::convert::TryInto;
+    use std::io::Read;
+
+    /// Splits a PNG into its chunks, checking every checksum along the way
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], PNG_SIGNATURE);
+        let mut rest = &png[8..];
+        let mut chunks = Vec::new();
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+            let data = rest[8..8 + len].to_vec();
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(&kind);
+            hasher.update(&data);
+            assert_eq!(hasher.finalize(), crc);
+            chunks.push((kind, data));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn encodes_png() {
+        let pixels: Vec<u8> = (0..3 * 2 * 4).map(|i| i as u8).collect();
+        let png = encode_png(3, 2, &pixels).unwrap();
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(k, _)| k).collect();
+        assert_eq!(kinds, vec![b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, vec![0, 0, 0, 3, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&chunks[1].1[..])
+            .read_to_end(&mut raw)
+            .unwrap();
+        let mut expected = vec![0];
+        expected.extend_from_slice(&pixels[..12]);
+        expected.push(0);
+        expected.extend_from_slice(&pixels[12..]);
+        assert_eq!(raw, expected);
+    }
+
+    #[test]
+    fn flips_rows() {
+        let mut pixels: Vec<u8> = (0..3 * 4).collect();
+        flip_rows(&mut pixels, 1);
+        assert_eq!(pixels, vec![8, 9, 10, 11, 4, 5, 6, 7, 0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn empty_images_dont_panic() {
+        let mut pixels = Vec::new();
+        flip_rows(&mut pixels, 0);
+        let err = encode_png(0, 5, &pixels).unwrap_err();
+        assert_eq!(err, "Can't save an empty 0x5 image");
+        assert!(encode_png(5, 0, &pixels).is_err());
+        let path = std::env::temp_dir().join("planets-empty.png");
+        assert!(save_png(&path, 0, 5, &pixels).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn renders_replay_frames() {
+        let dir = std::env::temp_dir().join(format!("planets-frames-{}", std::process::id()));
+        let mut recording = Recording::new(&Config::default(), Level::builtin());
+        for _ in 0..10 {
+            recording.record(&Input::default());
+        }
+        let mut renderer = SoftwareRenderer::new(16, 12);
+        let mut camera = Camera2D::new(na::Vector2::new(0.0, 80.0), 0.12);
+
+        let written =
+            render_replay_frames(&recording, &dir, Some(4), &mut renderer, &mut camera).unwrap();
+        assert_eq!(written, 4);
+        assert!(frame_path(&dir, 3).exists());
+        assert!(!frame_path(&dir, 4).exists());
+        let last = std::fs::read(frame_path(&dir, 3)).unwrap();
+        assert_eq!(last, encode_png(16, 12, renderer.pixels()).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
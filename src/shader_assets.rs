@@ -0,0 +1,206 @@
+use crate::gl_backend::GlBackend;
+use crate::gl_shaders::{Shader, ShaderProgram, ShaderType};
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Notices when files change on disk, by checking when they were last modified. Files that don't
+/// exist yet are watched too, and count as changed once they appear.
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl FileWatcher {
+    pub fn new(paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        FileWatcher {
+            files: paths
+                .into_iter()
+                .map(|path| {
+                    let time = modified(&path);
+                    (path, time)
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether any of the files were modified, created or deleted since the last call
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, time) in self.files.iter_mut() {
+            let now = modified(path);
+            if now != *time {
+                *time = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// GL's logs end in a nul and usually a newline
+fn clean_log(log: String) -> String {
+    log.trim_end_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string()
+}
+
+fn compile_stage(path: &Path, kind: ShaderType, backend: GlBackend) -> Result<Shader, String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read shader {}: {}", path.display(), e))?;
+    Shader::from_glsl(&source, kind, backend)
+        .map_err(|e| format!("{}:\n{}", path.display(), clean_log(e)))
+}
+
+/// Compiles and links a program from GLSL sources written for GL 3.3 core, in `backend`'s
+/// dialect. Errors say which stage went wrong.
+pub fn compile_program(
+    name: &str,
+    vertex_source: &str,
+    fragment_source: &str,
+    backend: GlBackend,
+) -> Result<ShaderProgram, String> {
+    let vertex = Shader::from_glsl(vertex_source, ShaderType::Vertex, backend)
+        .map_err(|e| format!("{} vertex shader:\n{}", name, clean_log(e)))?;
+    let fragment = Shader::from_glsl(fragment_source, ShaderType::Fragment, backend)
+        .map_err(|e| format!("{} fragment shader:\n{}", name, clean_log(e)))?;
+    ShaderProgram::from_shaders(&[vertex, fragment])
+        .map_err(|e| format!("{} failed to link:\n{}", name, clean_log(e)))
+}
+
+/// A shader program loaded from a vertex and a fragment shader file, compiled again whenever
+/// either file changes. When that fails, the last program that worked keeps being used and the
+/// error is kept around to be shown.
+pub struct ShaderAsset {
+    vertex_path: PathBuf,
+    fragment_path: PathBuf,
+    watcher: FileWatcher,
+    backend: GlBackend,
+    program: ShaderProgram,
+    error: Option<String>,
+}
+
+impl ShaderAsset {
+    /// Loads `files`, a vertex and a fragment shader, from `dir`. `builtin` is their source baked
+    /// into the game. It's used when the files are missing or broken to start with, so there's
+    /// always something to draw with. Only fails if the built in sources don't work either. Both
+    /// are written for GL 3.3 core and compiled in `backend`'s dialect.
+    pub fn load(
+        dir: &Path,
+        files: (&str, &str),
+        builtin: (&str, &str),
+        backend: GlBackend,
+    ) -> Result<ShaderAsset, String> {
+        let vertex_path = dir.join(files.0);
+        let fragment_path = dir.join(files.1);
+        let watcher = FileWatcher::new(vec![vertex_path.clone(), fragment_path.clone()]);
+        let (program, error) = match Self::compile_files(&vertex_path, &fragment_path, backend) {
+            Ok(program) => (program, None),
+            Err(e) => {
+                let name = format!("The built in {} and {}", files.0, files.1);
+                (
+                    compile_program(&name, builtin.0, builtin.1, backend)?,
+                    Some(e),
+                )
+            }
+        };
+        Ok(ShaderAsset {
+            vertex_path,
+            fragment_path,
+            watcher,
+            backend,
+            program,
+            error,
+        })
+    }
+
+    fn compile_files(
+        vertex_path: &Path,
+        fragment_path: &Path,
+        backend: GlBackend,
+    ) -> Result<ShaderProgram, String> {
+        let vertex = compile_stage(vertex_path, ShaderType::Vertex, backend)?;
+        let fragment = compile_stage(fragment_path, ShaderType::Fragment, backend)?;
+        ShaderProgram::from_shaders(&[vertex, fragment]).map_err(|e| {
+            format!(
+                "{} and {} failed to link:\n{}",
+                vertex_path.display(),
+                fragment_path.display(),
+                clean_log(e)
+            )
+        })
+    }
+
+    /// Compiles the files again if either changed since the last call, returns whether they did.
+    /// Needs a current GL context.
+    pub fn reload_if_changed(&mut self) -> bool {
+        if !self.watcher.changed() {
+            return false;
+        }
+        match Self::compile_files(&self.vertex_path, &self.fragment_path, self.backend) {
+            Ok(program) => {
+                self.program = program;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e),
+        }
+        true
+    }
+
+    /// The last program that compiled
+    pub fn program(&self) -> &ShaderProgram {
+        &self.program
+    }
+
+    /// Why the files on disk aren't being used, if they aren't
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn notices_changes() {
+        let dir = std::env::temp_dir().join(format!("planets-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.vert"), dir.join("b.frag"));
+        std::fs::write(&a, "one").unwrap();
+        let _ = std::fs::remove_file(&b);
+
+        let mut watcher = FileWatcher::new(vec![a.clone(), b.clone()]);
+        assert!(!watcher.changed());
+
+        // explicitly later, file times can be too coarse to tell quick writes apart
+        let later = modified(&a).unwrap() + Duration::from_secs(2);
+        std::fs::File::options()
+            .write(true)
+            .open(&a)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        std::fs::write(&b, "two").unwrap();
+        assert!(watcher.changed());
+        std::fs::remove_file(&b).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cleans_logs() {
+        assert_eq!(
+            clean_log("0:3(1): error: syntax error\n\0".to_string()),
+            "0:3(1): error: syntax error"
+        );
+    }
+}
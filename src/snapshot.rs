@@ -0,0 +1,756 @@
+use crate::camera::Camera2D;
+use crate::world::World;
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Bumped whenever anything saved in a snapshot changes shape, old snapshots are refused instead
+/// of loading into something broken
+pub const SNAPSHOT_VERSION: u32 = 6;
+
+/// The camera as the game currently keeps it, saved alongside the world
+pub type CameraState = Camera2D;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnapshotFormat {
+    /// Small and fast, for quicksaves
+    Binary,
+    /// Readable, for poking around in or diffing
+    Json,
+}
+
+impl SnapshotFormat {
+    /// Picks the format from a file's extension, anything other than `.json` is binary
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => SnapshotFormat::Json,
+            _ => SnapshotFormat::Binary,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    version: u32,
+    world: &'a World,
+    camera: &'a CameraState,
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    version: u32,
+    world: World,
+    camera: CameraState,
+}
+
+/// Just enough to check the version before trying to read the rest
+#[derive(Deserialize)]
+struct SnapshotHeader {
+    version: u32,
+}
+
+const BINARY_MAGIC: &[u8; 8] = b"PLNTSNAP";
+
+/// The broad phase keeps maps keyed by grid cells, which can't go through json as they are since
+/// json keys have to be strings. For human readable formats, maps like that are saved as lists of
+/// `[key, value]` pairs instead, and everything else as usual.
+pub(crate) mod maps_as_pairs {
+    use serde::de::value::{MapDeserializer, SeqDeserializer, StringDeserializer};
+    use serde::de::{
+        self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, VariantAccess,
+        Visitor,
+    };
+    use serde::ser::{self, Error as _};
+    use serde::{forward_to_deserialize_any, Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::{Error, Map, Number, Value};
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let value = value.serialize(ToValue).map_err(S::Error::custom)?;
+            value.serialize(serializer)
+        } else {
+            value.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, T: DeserializeOwned, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        if deserializer.is_human_readable() {
+            let value = Value::deserialize(deserializer)?;
+            T::deserialize(FromValue(value)).map_err(de::Error::custom)
+        } else {
+            T::deserialize(deserializer)
+        }
+    }
+
+    /// Like `serde_json::to_value`, but without failing on maps with keys that aren't strings
+    struct ToValue;
+
+    /// Wraps `value` in the externally tagged form json uses for enum variants
+    fn tagged(variant: Option<&'static str>, value: Value) -> Value {
+        match variant {
+            Some(variant) => {
+                let mut map = Map::new();
+                map.insert(variant.to_string(), value);
+                Value::Object(map)
+            }
+            None => value,
+        }
+    }
+
+    struct SeqToValue {
+        variant: Option<&'static str>,
+        items: Vec<Value>,
+    }
+
+    struct MapToValue {
+        variant: Option<&'static str>,
+        entries: Vec<(Value, Value)>,
+    }
+
+    impl SeqToValue {
+        fn new(variant: Option<&'static str>, len: usize) -> Self {
+            SeqToValue {
+                variant,
+                items: Vec::with_capacity(len),
+            }
+        }
+
+        fn push<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+            self.items.push(value.serialize(ToValue)?);
+            Ok(())
+        }
+
+        fn finish(self) -> Result<Value, Error> {
+            Ok(tagged(self.variant, Value::Array(self.items)))
+        }
+    }
+
+    impl MapToValue {
+        fn new(variant: Option<&'static str>, len: usize) -> Self {
+            MapToValue {
+                variant,
+                entries: Vec::with_capacity(len),
+            }
+        }
+
+        fn field<T: ?Sized + Serialize>(
+            &mut self,
+            name: &'static str,
+            value: &T,
+        ) -> Result<(), Error> {
+            let value = value.serialize(ToValue)?;
+            self.entries.push((Value::String(name.to_string()), value));
+            Ok(())
+        }
+
+        fn finish(self) -> Result<Value, Error> {
+            let value = if self.entries.iter().all(|(key, _)| key.is_string()) {
+                let mut map = Map::new();
+                for (key, value) in self.entries {
+                    if let Value::String(key) = key {
+                        map.insert(key, value);
+                    }
+                }
+                Value::Object(map)
+            } else {
+                let pairs = self.entries.into_iter();
+                Value::Array(pairs.map(|(k, v)| Value::Array(vec![k, v])).collect())
+            };
+            Ok(tagged(self.variant, value))
+        }
+    }
+
+    impl Serializer for ToValue {
+        type Ok = Value;
+        type Error = Error;
+        type SerializeSeq = SeqToValue;
+        type SerializeTuple = SeqToValue;
+        type SerializeTupleStruct = SeqToValue;
+        type SerializeTupleVariant = SeqToValue;
+        type SerializeMap = MapToValue;
+        type SerializeStruct = MapToValue;
+        type SerializeStructVariant = MapToValue;
+
+        fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+            Ok(Value::Bool(v))
+        }
+
+        fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+            self.serialize_i64(v.into())
+        }
+
+        fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+            self.serialize_i64(v.into())
+        }
+
+        fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+            self.serialize_i64(v.into())
+        }
+
+        fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+            Ok(Value::from(v))
+        }
+
+        fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+            self.serialize_u64(v.into())
+        }
+
+        fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+            self.serialize_u64(v.into())
+        }
+
+        fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+            self.serialize_u64(v.into())
+        }
+
+        fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+            Ok(Value::from(v))
+        }
+
+        fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+            self.serialize_f64(v.into())
+        }
+
+        fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+            Number::from_f64(v)
+                .map(Value::Number)
+                .ok_or_else(|| Error::custom(format!("{} can't be saved as json", v)))
+        }
+
+        fn serialize_char(self, v: char) -> Result<Value, Error> {
+            Ok(Value::String(v.to_string()))
+        }
+
+        fn serialize_str(self, v: &str) -> Result<Value, Error> {
+            Ok(Value::String(v.to_string()))
+        }
+
+        fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+            Ok(Value::Array(v.iter().map(|&b| Value::from(b)).collect()))
+        }
+
+        fn serialize_none(self) -> Result<Value, Error> {
+            Ok(Value::Null)
+        }
+
+        fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Value, Error> {
+            value.serialize(self)
+        }
+
+        fn serialize_unit(self) -> Result<Value, Error> {
+            Ok(Value::Null)
+        }
+
+        fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+            Ok(Value::Null)
+        }
+
+        fn serialize_unit_variant(
+            self,
+            _name: &'static str,
+            _index: u32,
+            variant: &'static str,
+        ) -> Result<Value, Error> {
+            Ok(Value::String(variant.to_string()))
+        }
+
+        fn serialize_newtype_struct<T: ?Sized + Serialize>(
+            self,
+            _name: &'static str,
+            value: &T,
+        ) -> Result<Value, Error> {
+            value.serialize(self)
+        }
+
+        fn serialize_newtype_variant<T: ?Sized + Serialize>(
+            self,
+            _name: &'static str,
+            _index: u32,
+            variant: &'static str,
+            value: &T,
+        ) -> Result<Value, Error> {
+            Ok(tagged(Some(variant), value.serialize(self)?))
+        }
+
+        fn serialize_seq(self, len: Option<usize>) -> Result<SeqToValue, Error> {
+            Ok(SeqToValue::new(None, len.unwrap_or(0)))
+        }
+
+        fn serialize_tuple(self, len: usize) -> Result<SeqToValue, Error> {
+            Ok(SeqToValue::new(None, len))
+        }
+
+        fn serialize_tuple_struct(
+            self,
+            _name: &'static str,
+            len: usize,
+        ) -> Result<SeqToValue, Error> {
+            Ok(SeqToValue::new(None, len))
+        }
+
+        fn serialize_tuple_variant(
+            self,
+            _name: &'static str,
+            _index: u32,
+            variant: &'static str,
+            len: usize,
+        ) -> Result<SeqToValue, Error> {
+            Ok(SeqToValue::new(Some(variant), len))
+        }
+
+        fn serialize_map(self, len: Option<usize>) -> Result<MapToValue, Error> {
+            Ok(MapToValue::new(None, len.unwrap_or(0)))
+        }
+
+        fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapToValue, Error> {
+            Ok(MapToValue::new(None, len))
+        }
+
+        fn serialize_struct_variant(
+            self,
+            _name: &'static str,
+            _index: u32,
+            variant: &'static str,
+            len: usize,
+        ) -> Result<MapToValue, Error> {
+            Ok(MapToValue::new(Some(variant), len))
+        }
+    }
+
+    impl ser::SerializeSeq for SeqToValue {
+        type Ok = Value;
+        type Error = Error;
+
+        fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+            self.push(value)
+        }
+
+        fn end(self) -> Result<Value, Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeTuple for SeqToValue {
+        type Ok = Value;
+        type Error = Error;
+
+        fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+            self.push(value)
+        }
+
+        fn end(self) -> Result<Value, Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeTupleStruct for SeqToValue {
+        type Ok = Value;
+        type Error = Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+            self.push(value)
+        }
+
+        fn end(self) -> Result<Value, Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeTupleVariant for SeqToValue {
+        type Ok = Value;
+        type Error = Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+            self.push(value)
+        }
+
+        fn end(self) -> Result<Value, Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeMap for MapToValue {
+        type Ok = Value;
+        type Error = Error;
+
+        fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), Error> {
+            self.entries.push((key.serialize(ToValue)?, Value::Null));
+            Ok(())
+        }
+
+        fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), Error> {
+            let entry = self
+                .entries
+                .last_mut()
+                .expect("value serialized before its key");
+            entry.1 = value.serialize(ToValue)?;
+            Ok(())
+        }
+
+        fn end(self) -> Result<Value, Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeStruct for MapToValue {
+        type Ok = Value;
+        type Error = Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(
+            &mut self,
+            name: &'static str,
+            value: &T,
+        ) -> Result<(), Error> {
+            self.field(name, value)
+        }
+
+        fn end(self) -> Result<Value, Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeStructVariant for MapToValue {
+        type Ok = Value;
+        type Error = Error;
+
+        fn serialize_field<T: ?Sized + Serialize>(
+            &mut self,
+            name: &'static str,
+            value: &T,
+        ) -> Result<(), Error> {
+            self.field(name, value)
+        }
+
+        fn end(self) -> Result<Value, Error> {
+            self.finish()
+        }
+    }
+
+    /// Reads back what `ToValue` wrote, taking lists of pairs for maps
+    struct FromValue(Value);
+
+    impl<'de> IntoDeserializer<'de, Error> for FromValue {
+        type Deserializer = Self;
+
+        fn into_deserializer(self) -> Self {
+            self
+        }
+    }
+
+    fn visit_seq<'de, V: Visitor<'de>>(items: Vec<Value>, visitor: V) -> Result<V::Value, Error> {
+        let mut seq = SeqDeserializer::new(items.into_iter().map(FromValue));
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(value)
+    }
+
+    fn visit_map<'de, V: Visitor<'de>>(
+        entries: impl Iterator<Item = (Value, Value)>,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let mut map = MapDeserializer::new(entries.map(|(k, v)| (FromValue(k), FromValue(v))));
+        let value = visitor.visit_map(&mut map)?;
+        map.end()?;
+        Ok(value)
+    }
+
+    impl<'de> Deserializer<'de> for FromValue {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self.0 {
+                Value::Null => visitor.visit_unit(),
+                Value::Bool(v) => visitor.visit_bool(v),
+                Value::Number(n) => {
+                    if let Some(v) = n.as_u64() {
+                        visitor.visit_u64(v)
+                    } else if let Some(v) = n.as_i64() {
+                        visitor.visit_i64(v)
+                    } else {
+                        visitor.visit_f64(n.as_f64().unwrap_or(f64::NAN))
+                    }
+                }
+                Value::String(v) => visitor.visit_string(v),
+                Value::Array(items) => visit_seq(items, visitor),
+                Value::Object(map) => {
+                    visit_map(map.into_iter().map(|(k, v)| (Value::String(k), v)), visitor)
+                }
+            }
+        }
+
+        fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self.0 {
+                Value::Array(pairs) => {
+                    let entries = pairs
+                        .into_iter()
+                        .map(|pair| match pair {
+                            Value::Array(mut pair) if pair.len() == 2 => {
+                                let value = pair.pop().unwrap();
+                                Ok((pair.pop().unwrap(), value))
+                            }
+                            _ => Err(Error::custom("expected a [key, value] pair")),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    visit_map(entries.into_iter(), visitor)
+                }
+                value => FromValue(value).deserialize_any(visitor),
+            }
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            match self.0 {
+                Value::Null => visitor.visit_none(),
+                value => visitor.visit_some(FromValue(value)),
+            }
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            match self.0 {
+                Value::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+                Value::Object(map) if map.len() == 1 => {
+                    let (variant, value) = map.into_iter().next().unwrap();
+                    visitor.visit_enum(Variant(variant, value))
+                }
+                _ => Err(Error::custom("expected an enum variant")),
+            }
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit
+            unit_struct seq tuple tuple_struct struct identifier ignored_any
+        }
+    }
+
+    /// An enum variant's name, and what it holds
+    struct Variant(String, Value);
+
+    impl<'de> EnumAccess<'de> for Variant {
+        type Error = Error;
+        type Variant = FromValue;
+
+        fn variant_seed<V: DeserializeSeed<'de>>(
+            self,
+            seed: V,
+        ) -> Result<(V::Value, FromValue), Error> {
+            let name: StringDeserializer<Error> = self.0.into_deserializer();
+            Ok((seed.deserialize(name)?, FromValue(self.1)))
+        }
+    }
+
+    impl<'de> VariantAccess<'de> for FromValue {
+        type Error = Error;
+
+        fn unit_variant(self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+            seed.deserialize(self)
+        }
+
+        fn tuple_variant<V: Visitor<'de>>(
+            self,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.deserialize_seq(visitor)
+        }
+
+        fn struct_variant<V: Visitor<'de>>(
+            self,
+            _fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.deserialize_map(visitor)
+        }
+    }
+}
+
+fn check_version(version: u32) -> Result<(), String> {
+    if version != SNAPSHOT_VERSION {
+        return Err(format!(
+            "Snapshot is version {}, but only version {} can be loaded",
+            version, SNAPSHOT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+impl World {
+    /// Serializes everything needed to carry on simulating exactly where this world is now: every
+    /// body, collider and joint, the broad and narrow phase caches, planets and the player's
+    /// controller state. `camera` is saved with it.
+    pub fn snapshot(
+        &self,
+        camera: &CameraState,
+        format: SnapshotFormat,
+    ) -> Result<Vec<u8>, String> {
+        let snapshot = SnapshotRef {
+            version: SNAPSHOT_VERSION,
+            world: self,
+            camera,
+        };
+        match format {
+            SnapshotFormat::Binary => {
+                let mut bytes = BINARY_MAGIC.to_vec();
+                bincode::serialize_into(&mut bytes, &snapshot)
+                    .map_err(|e| format!("Failed to write snapshot: {}", e))?;
+                Ok(bytes)
+            }
+            SnapshotFormat::Json => serde_json::to_vec_pretty(&snapshot)
+                .map_err(|e| format!("Failed to write snapshot: {}", e)),
+        }
+    }
+
+    /// The opposite of `snapshot`
+    pub fn restore(bytes: &[u8], format: SnapshotFormat) -> Result<(World, CameraState), String> {
+        let snapshot: Snapshot = match format {
+            SnapshotFormat::Binary => {
+                if !bytes.starts_with(BINARY_MAGIC) {
+                    return Err("Not a binary snapshot".to_string());
+                }
+                let bytes = &bytes[BINARY_MAGIC.len()..];
+                // the version is always serialized first
+                let header: SnapshotHeader =
+                    bincode::deserialize(bytes).map_err(|e| format!("Invalid snapshot: {}", e))?;
+                check_version(header.version)?;
+                bincode::deserialize(bytes).map_err(|e| format!("Invalid snapshot: {}", e))?
+            }
+            SnapshotFormat::Json => {
+                let header: SnapshotHeader = serde_json::from_slice(bytes)
+                    .map_err(|e| format!("Invalid snapshot: {}", e))?;
+                check_version(header.version)?;
+                serde_json::from_slice(bytes).map_err(|e| format!("Invalid snapshot: {}", e))?
+            }
+        };
+        debug_assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        let mut world = snapshot.world;
+        world.update_query_pipeline();
+        Ok((world, snapshot.camera))
+    }
+
+    pub fn save_snapshot(
+        &self,
+        camera: &CameraState,
+        path: impl AsRef<Path>,
+    ) -> Result<(), String> {
+        let path = path.as_ref();
+        let bytes = self.snapshot(camera, SnapshotFormat::from_path(path))?;
+        std::fs::write(path, bytes)
+            .map_err(|e| format!("Failed to write snapshot {}: {}", path.display(), e))
+    }
+
+    pub fn load_snapshot(path: impl AsRef<Path>) -> Result<(World, CameraState), String> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("Failed to read snapshot {}: {}", path.display(), e))?;
+        Self::restore(&bytes, SnapshotFormat::from_path(path))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Input, WorldConfig};
+
+    fn input(i: usize) -> Input {
+        Input {
+            horizontal_movement: if (i / 100).is_multiple_of(2) {
+                1.0
+            } else {
+                -1.0
+            },
+            jump: i.is_multiple_of(90),
+            jump_held: i % 90 < 20,
+        }
+    }
+
+    fn run(world: &mut World, steps: std::ops::Range<usize>) {
+        for i in steps {
+            world.step(&input(i));
+        }
+    }
+
+    fn round_trip(format: SnapshotFormat) {
+        let mut camera = CameraState::new(na::Vector2::new(1.0, 2.0), 3.0);
+        camera.rotation = 0.5;
+        let mut world = World::new(WorldConfig::default());
+        run(&mut world, 0..250);
+
+        let bytes = world.snapshot(&camera, format).unwrap();
+        let (mut restored, restored_camera) = World::restore(&bytes, format).unwrap();
+        assert_eq!(restored_camera, camera);
+        assert_eq!(restored.state_hash(), world.state_hash());
+
+        // mid jump and all, both carry on the same way
+        run(&mut world, 250..600);
+        run(&mut restored, 250..600);
+        assert_eq!(restored.state_hash(), world.state_hash());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        round_trip(SnapshotFormat::Binary);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip(SnapshotFormat::Json);
+    }
+
+    #[test]
+    fn json_has_no_opaque_parts() {
+        let mut world = World::new(WorldConfig::default());
+        run(&mut world, 0..10);
+        let camera = CameraState::new(na::Vector2::zeros(), 1.0);
+        let bytes = world.snapshot(&camera, SnapshotFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        // grid cells are [x, y], so their maps are saved as pairs
+        let regions = &json["world"]["broad_phase"]["layers"][0]["regions"];
+        let first = &regions.as_array().unwrap()[0];
+        assert!(first[0][0].is_i64() && first[0][1].is_i64(), "{}", first);
+        assert!(first[1].is_u64(), "{}", first);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let world = World::new(WorldConfig::default());
+        let camera = CameraState::new(na::Vector2::zeros(), 1.0);
+
+        let json =
+            String::from_utf8(world.snapshot(&camera, SnapshotFormat::Json).unwrap()).unwrap();
+        let json = json.replacen(
+            &format!("\"version\": {}", SNAPSHOT_VERSION),
+            "\"version\": 9999",
+            1,
+        );
+        let err = World::restore(json.as_bytes(), SnapshotFormat::Json)
+            .err()
+            .unwrap();
+        assert!(err.contains("version 9999"), "{}", err);
+
+        let mut binary = world.snapshot(&camera, SnapshotFormat::Binary).unwrap();
+        binary[BINARY_MAGIC.len()] = 0xff;
+        assert!(World::restore(&binary, SnapshotFormat::Binary).is_err());
+        assert!(World::restore(b"garbage", SnapshotFormat::Binary).is_err());
+    }
+}
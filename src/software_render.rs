@@ -0,0 +1,292 @@
+use crate::render::{round_rect_color, Color, DrawStats, RenderBackend, RoundRect, Style};
+
+type P2 = na::Point2<f32>;
+type V2 = na::Vector2<f32>;
+
+/// Draws into an RGBA buffer on the CPU, so frames can be looked at without a GPU. Pixels are
+/// sampled at their centers, round rects get the same smooth edges the GL shader gives them.
+pub struct SoftwareRenderer {
+    width: usize,
+    height: usize,
+    /// Four bytes per pixel, rows from the top of the image down
+    pixels: Vec<u8>,
+    /// From world coordinates to pixels
+    to_pixels: na::Matrix3<f32>,
+    from_pixels: na::Matrix3<f32>,
+    /// How big a pixel is in world units
+    pixel_size: f32,
+    stats: DrawStats,
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: usize, height: usize) -> Self {
+        SoftwareRenderer {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+            to_pixels: na::Matrix3::identity(),
+            from_pixels: na::Matrix3::identity(),
+            pixel_size: 1.0,
+            stats: DrawStats::default(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    /// The image as text, one character per pixel: `#` for dark, `+` for in between and `.` for
+    /// light. Small enough frames can be checked against these in tests.
+    pub fn ascii_art(&self) -> String {
+        let mut art = String::with_capacity((self.width + 1) * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let [r, g, b, _] = self.pixel(x, y);
+                let brightness = (r as f32 + g as f32 + b as f32) / (3.0 * 255.0);
+                art.push(if brightness < 0.25 {
+                    '#'
+                } else if brightness < 0.75 {
+                    '+'
+                } else {
+                    '.'
+                });
+            }
+            art.push('\n');
+        }
+        art
+    }
+
+    fn blend(&mut self, x: usize, y: usize, color: Color) {
+        let i = (y * self.width + x) * 4;
+        let alpha = color[3].clamp(0.0, 1.0);
+        for (old, new) in self.pixels[i..i + 3].iter_mut().zip(color.iter()) {
+            let blended = new * alpha + (*old as f32 / 255.0) * (1.0 - alpha);
+            *old = (blended * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        let old_alpha = self.pixels[i + 3] as f32 / 255.0;
+        self.pixels[i + 3] = ((alpha + old_alpha * (1.0 - alpha)) * 255.0).round() as u8;
+    }
+
+    /// Calls `shade` with the world position of the center of every pixel inside the bounding
+    /// box of `points` (in world coordinates), and blends in the color it returns, if any
+    fn fill_where(&mut self, points: &[V2], shade: impl Fn(V2) -> Option<Color>) {
+        let mut min = V2::new(f32::MAX, f32::MAX);
+        let mut max = V2::new(f32::MIN, f32::MIN);
+        for p in points {
+            let p = self.to_pixels.transform_point(&P2::from(*p));
+            min = min.inf(&p.coords);
+            max = max.sup(&p.coords);
+        }
+        let x_range =
+            min.x.floor().max(0.0) as usize..(max.x.ceil().max(0.0) as usize).min(self.width);
+        let y_range =
+            min.y.floor().max(0.0) as usize..(max.y.ceil().max(0.0) as usize).min(self.height);
+        for y in y_range {
+            for x in x_range.clone() {
+                let center = P2::new(x as f32 + 0.5, y as f32 + 0.5);
+                if let Some(color) = shade(self.from_pixels.transform_point(&center).coords) {
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+}
+
+impl RenderBackend for SoftwareRenderer {
+    fn begin_frame(
+        &mut self,
+        projection: &na::Matrix4<f32>,
+        camera: &na::Matrix4<f32>,
+        clear: Color,
+    ) {
+        // only x and y of an orthographic projection matter, which is an affine transform in 2d
+        let clip = projection * camera;
+        let clip = na::Matrix3::new(
+            clip[(0, 0)],
+            clip[(0, 1)],
+            clip[(0, 3)],
+            clip[(1, 0)],
+            clip[(1, 1)],
+            clip[(1, 3)],
+            0.0,
+            0.0,
+            1.0,
+        );
+        let (w, h) = (self.width as f32, self.height as f32);
+        let viewport =
+            na::Matrix3::new(w / 2.0, 0.0, w / 2.0, 0.0, -h / 2.0, h / 2.0, 0.0, 0.0, 1.0);
+        self.to_pixels = viewport * clip;
+        self.from_pixels = self
+            .to_pixels
+            .try_inverse()
+            .unwrap_or_else(na::Matrix3::zeros);
+        self.pixel_size = V2::new(self.from_pixels[(0, 0)], self.from_pixels[(1, 0)]).norm();
+
+        let clear: Vec<u8> = clear
+            .iter()
+            .map(|c| (c * 255.0).round().clamp(0.0, 255.0) as u8)
+            .collect();
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel.copy_from_slice(&clear);
+        }
+        self.stats = DrawStats::default();
+    }
+
+    fn fill_round_rect(&mut self, rect: &RoundRect, style: &Style) {
+        self.stats.shapes += 1;
+        self.stats.vertices += 4;
+        let corners: Vec<V2> = rect
+            .corners(self.pixel_size)
+            .iter()
+            .map(|(corner, _)| *corner)
+            .collect();
+        let pixel_size = self.pixel_size;
+        self.fill_where(&corners, |p| {
+            round_rect_color(style, rect.distance(p), pixel_size)
+        });
+    }
+
+    fn fill_polygon(&mut self, points: &[V2], color: Color) {
+        self.stats.shapes += 1;
+        self.stats.vertices += points.len() as u32;
+        if points.len() < 3 {
+            return;
+        }
+        let edges: Vec<(V2, V2)> = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| (*a, *b))
+            .collect();
+        // inside a convex polygon means being on the same side of every edge
+        self.fill_where(points, |p| {
+            let mut sides = edges.iter().map(|(a, b)| (b - a).perp(&(p - a)));
+            if sides.clone().all(|s| s >= 0.0) || sides.all(|s| s <= 0.0) {
+                Some(color)
+            } else {
+                None
+            }
+        });
+    }
+
+    /// Everything is drawn right away, so there's never any draw calls
+    fn end_frame(&mut self) -> DrawStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{draw_world, Style, BLACK, WHITE};
+    use crate::world::{Input, World, WorldConfig};
+    use rapier2d_f64::geometry::Cuboid;
+    use rapier2d_f64::na::Isometry2;
+
+    /// Compares against `tests/golden/<name>.txt`, run with `UPDATE_GOLDEN=1` to write the file
+    /// instead after an intended change
+    fn assert_golden(name: &str, renderer: &SoftwareRenderer) {
+        let path = format!("{}/tests/golden/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+        let art = renderer.ascii_art();
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&path, &art).unwrap();
+            return;
+        }
+        let golden = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("{}: {}, run with UPDATE_GOLDEN=1 to create it", path, e));
+        assert!(
+            art == golden,
+            "{} doesn't match, got:\n{}\nexpected:\n{}",
+            path,
+            art,
+            golden
+        );
+    }
+
+    /// Pixels and world units are the same, with the origin in the top left
+    fn pixel_space(renderer: &SoftwareRenderer) -> na::Matrix4<f32> {
+        na::Orthographic3::new(
+            0.0,
+            renderer.width() as f32,
+            renderer.height() as f32,
+            0.0,
+            -1.0,
+            1.0,
+        )
+        .to_homogeneous()
+    }
+
+    #[test]
+    fn fills_shapes() {
+        let mut renderer = SoftwareRenderer::new(8, 8);
+        let projection = pixel_space(&renderer);
+        renderer.begin_frame(&projection, &na::Matrix4::identity(), WHITE);
+        renderer.fill_rect(V2::new(1.0, 1.0), V2::new(3.0, 4.0), BLACK);
+        renderer.fill_circle(V2::new(6.0, 6.0), 1.0, [1.0, 0.0, 0.0, 0.5]);
+        let stats = renderer.end_frame();
+        assert_eq!(stats.shapes, 2);
+
+        assert_eq!(renderer.pixel(0, 0), [255, 255, 255, 255]);
+        assert_eq!(renderer.pixel(1, 1), [0, 0, 0, 255]);
+        assert_eq!(renderer.pixel(2, 3), [0, 0, 0, 255]);
+        assert_eq!(renderer.pixel(3, 3), [255, 255, 255, 255]);
+        // half transparent red over white, a bit less since the pixel's near the edge
+        assert_eq!(renderer.pixel(5, 5), [255, 142, 142, 255]);
+        assert_eq!(
+            renderer.ascii_art(),
+            "........\n.##.....\n.##.....\n.##.....\n........\n.....++.\n.....++.\n........\n"
+        );
+    }
+
+    #[test]
+    fn golden_shapes() {
+        let mut renderer = SoftwareRenderer::new(40, 20);
+        let projection = pixel_space(&renderer);
+        renderer.begin_frame(&projection, &na::Matrix4::identity(), WHITE);
+        renderer.fill_circle(V2::new(6.0, 6.0), 5.0, BLACK);
+        renderer.draw_shape(
+            &Cuboid::new(na::Vector2::new(5.0, 3.0)),
+            &Isometry2::new(na::Vector2::new(19.0, 5.0), 0.4),
+            &Style::default(),
+        );
+        renderer.line(V2::new(28.0, 2.0), V2::new(38.0, 10.0), 1.5, BLACK);
+        renderer.text(V2::new(2.0, 12.0), "Hi 42", 7.0, BLACK);
+        renderer.end_frame();
+        assert_golden("shapes", &renderer);
+    }
+
+    #[test]
+    fn golden_world() {
+        let mut world = World::new(WorldConfig::default());
+        for _ in 0..300 {
+            world.step(&Input::default());
+        }
+        let mut renderer = SoftwareRenderer::new(96, 72);
+        let projection = pixel_space(&renderer);
+        // centered a bit above the planet, where the player lands
+        let camera = na::Matrix4::new_translation(&na::Vector3::new(48.0, 36.0, 0.0))
+            * na::Matrix4::new_scaling(0.8)
+            * na::Matrix4::new_translation(&na::Vector3::new(0.0, -100.0, 0.0));
+        renderer.begin_frame(&projection, &camera, WHITE);
+        draw_world(&world, &mut renderer, 1.0);
+        renderer.end_frame();
+        assert_golden("world", &renderer);
+    }
+}
@@ -0,0 +1,159 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// Source of the current time, so the game loop can be driven by something other than the wall
+/// clock in tests
+pub trait Clock {
+    /// Time elapsed since some fixed point in the past
+    fn now(&self) -> Duration;
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// A clock that only moves when told to
+#[derive(Default)]
+pub struct ManualClock {
+    now: Cell<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+/// Accumulates real time and hands it out in fixed `dt` sized physics steps
+pub struct FixedTimestep {
+    dt: f64,
+    max_substeps: u32,
+    accumulator: f64,
+    last_time: Option<Duration>,
+}
+
+impl FixedTimestep {
+    /// # Arguments
+    ///
+    /// * `dt` - Length of a single step in seconds, usually `IntegrationParameters::dt`
+    /// * `max_substeps` - The most steps `advance` will ever ask for. When the simulation can't
+    ///   keep up the extra time is thrown away instead of piling up (the "spiral of death")
+    pub fn new(dt: f64, max_substeps: u32) -> Self {
+        assert!(dt > 0.0, "Timestep must be positive! You gave me: {}", dt);
+        FixedTimestep {
+            dt,
+            max_substeps,
+            accumulator: 0.0,
+            last_time: None,
+        }
+    }
+
+    /// Adds the time passed since the last call and returns how many steps should be simulated
+    /// this frame. The first call only starts the clock.
+    pub fn advance(&mut self, clock: &impl Clock) -> u32 {
+        let now = clock.now();
+        let frame_time = match self.last_time {
+            Some(last_time) => now.saturating_sub(last_time).as_secs_f64(),
+            None => 0.0,
+        };
+        self.last_time = Some(now);
+
+        self.accumulator += frame_time.min(self.dt * self.max_substeps as f64);
+        let mut steps = 0;
+        while self.accumulator >= self.dt && steps < self.max_substeps {
+            self.accumulator -= self.dt;
+            steps += 1;
+        }
+        steps
+    }
+
+    /// How far between the previous and the current step the present moment is, from 0.0 to 1.0.
+    /// Used to interpolate what is drawn.
+    pub fn alpha(&self) -> f64 {
+        (self.accumulator / self.dt).min(1.0)
+    }
+
+    /// How long until `advance` will next return at least one step
+    pub fn time_until_next_step(&self) -> Duration {
+        Duration::from_secs_f64((self.dt - self.accumulator).max(0.0))
+    }
+
+    pub fn dt(&self) -> f64 {
+        self.dt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f64 = 1.0 / 60.0;
+
+    #[test]
+    fn steps_match_elapsed_time() {
+        let clock = ManualClock::new();
+        let mut timestep = FixedTimestep::new(DT, 8);
+        assert_eq!(timestep.advance(&clock), 0);
+
+        let mut total = 0;
+        for _ in 0..100 {
+            // frames that don't line up with dt at all
+            clock.advance(Duration::from_secs_f64(DT * 0.7));
+            total += timestep.advance(&clock);
+        }
+        assert_eq!(total, 70);
+        assert!(timestep.alpha() >= 0.0 && timestep.alpha() < 1.0);
+    }
+
+    #[test]
+    fn alpha_is_leftover_fraction() {
+        let clock = ManualClock::new();
+        let mut timestep = FixedTimestep::new(DT, 8);
+        timestep.advance(&clock);
+        clock.advance(Duration::from_secs_f64(DT * 2.25));
+        assert_eq!(timestep.advance(&clock), 2);
+        assert!((timestep.alpha() - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn long_frames_are_capped() {
+        let clock = ManualClock::new();
+        let mut timestep = FixedTimestep::new(DT, 5);
+        timestep.advance(&clock);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(timestep.advance(&clock), 5);
+
+        // the lost time is not paid back later
+        clock.advance(Duration::from_secs_f64(DT * 0.5));
+        assert_eq!(timestep.advance(&clock), 0);
+    }
+}
@@ -0,0 +1,2 @@
+pub type P2 = na::Point2<f64>;
+pub type V2 = na::Vector2<f64>;
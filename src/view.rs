@@ -0,0 +1,135 @@
+type V2 = na::Vector2<f32>;
+
+/// What happens to the picture when the window isn't the shape the game was made for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScaleMode {
+    /// Bigger windows show more of the world, at the same size
+    Expand,
+    /// Always shows a screen this many units across, as big as fits in the window, with bars
+    /// filling the rest
+    Letterbox { width: f32, height: f32 },
+}
+
+/// Where in the window things are drawn, and how. Window coordinates are the ones SDL gives mouse
+/// events in, which on high DPI displays are fewer than the drawable's pixels. Screen coordinates
+/// are what the camera works in, see `Camera2D`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+    /// x, y, width and height in drawable pixels, from the bottom left like `gl::Viewport` takes
+    /// them
+    pub viewport: [i32; 4],
+    /// How big the screen is in screen coordinates
+    pub screen_size: V2,
+    /// Where the screen's top left corner is in window coordinates
+    window_offset: V2,
+    /// Window coordinates per screen coordinate
+    window_scale: f32,
+}
+
+impl View {
+    /// Works out the view for a window `window` big, which is `drawable` big in pixels
+    pub fn new(window: (u32, u32), drawable: (u32, u32), mode: ScaleMode) -> View {
+        let window = V2::new(window.0 as f32, window.1 as f32).sup(&V2::new(1.0, 1.0));
+        let drawable = V2::new(drawable.0 as f32, drawable.1 as f32);
+        let pixels_per_point = drawable.component_div(&window);
+        let (screen_size, window_scale) = match mode {
+            ScaleMode::Expand => (window, 1.0),
+            ScaleMode::Letterbox { width, height } => {
+                let screen = V2::new(width, height);
+                (screen, (window.x / width).min(window.y / height))
+            }
+        };
+        let window_offset = (window - screen_size * window_scale) / 2.0;
+        let offset = window_offset.component_mul(&pixels_per_point);
+        let size = (screen_size * window_scale).component_mul(&pixels_per_point);
+        View {
+            // the bars are the same on both sides, so it doesn't matter that y goes up in GL
+            viewport: [
+                offset.x.round() as i32,
+                offset.y.round() as i32,
+                size.x.round() as i32,
+                size.y.round() as i32,
+            ],
+            screen_size,
+            window_offset,
+            window_scale,
+        }
+    }
+
+    /// Goes with `Camera2D::matrix`, taking screen coordinates to clip space
+    pub fn projection(&self) -> na::Matrix4<f32> {
+        na::Orthographic3::new(0.0, self.screen_size.x, self.screen_size.y, 0.0, -1.0, 1.0)
+            .to_homogeneous()
+    }
+
+    /// Where a point in window coordinates, like the mouse, is on the screen
+    pub fn window_to_screen(&self, point: V2) -> V2 {
+        (point - self.window_offset) / self.window_scale
+    }
+
+    /// If there are bars around the picture
+    pub fn is_letterboxed(&self, drawable: (u32, u32)) -> bool {
+        self.viewport != [0, 0, drawable.0 as i32, drawable.1 as i32]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_to_fill_the_window() {
+        let view = View::new((1000, 900), (1000, 900), ScaleMode::Expand);
+        assert_eq!(view.viewport, [0, 0, 1000, 900]);
+        assert_eq!(view.screen_size, V2::new(1000.0, 900.0));
+        assert!(!view.is_letterboxed((1000, 900)));
+        let clip = view
+            .projection()
+            .transform_point(&na::Point3::new(1000.0, 0.0, 0.0));
+        assert_eq!(clip, na::Point3::new(1.0, 1.0, 0.0));
+
+        // twice the pixels on a high DPI display, but just as much of the world
+        let view = View::new((1000, 900), (2000, 1800), ScaleMode::Expand);
+        assert_eq!(view.viewport, [0, 0, 2000, 1800]);
+        assert_eq!(view.screen_size, V2::new(1000.0, 900.0));
+        assert_eq!(
+            view.window_to_screen(V2::new(10.0, 20.0)),
+            V2::new(10.0, 20.0)
+        );
+    }
+
+    #[test]
+    fn letterboxes_to_keep_the_shape() {
+        let mode = ScaleMode::Letterbox {
+            width: 100.0,
+            height: 50.0,
+        };
+        // too wide, bars on the sides
+        let view = View::new((400, 100), (800, 200), mode);
+        assert_eq!(view.screen_size, V2::new(100.0, 50.0));
+        assert_eq!(view.viewport, [200, 0, 400, 200]);
+        assert!(view.is_letterboxed((800, 200)));
+        assert_eq!(view.window_to_screen(V2::new(100.0, 0.0)), V2::zeros());
+        assert_eq!(
+            view.window_to_screen(V2::new(300.0, 100.0)),
+            V2::new(100.0, 50.0)
+        );
+
+        // too tall, bars above and below
+        let view = View::new((100, 150), (100, 150), mode);
+        assert_eq!(view.viewport, [0, 50, 100, 50]);
+        // the world stays the same size on screen whatever the window does
+        assert_eq!(view.screen_size, V2::new(100.0, 50.0));
+        assert_eq!(
+            view.window_to_screen(V2::new(50.0, 75.0)),
+            V2::new(50.0, 25.0)
+        );
+    }
+
+    #[test]
+    fn survives_an_empty_window() {
+        let view = View::new((0, 0), (0, 0), ScaleMode::Expand);
+        assert_eq!(view.viewport, [0, 0, 0, 0]);
+        assert!(view.screen_size.x > 0.0 && view.screen_size.y > 0.0);
+    }
+}
@@ -0,0 +1,428 @@
+use crate::gravity::{Gravity, Planet};
+use crate::level::{Level, Visual};
+use crate::player_controller::{ControllerConfig, PlayerController};
+use crate::util::*;
+
+use rapier2d_f64::dynamics::{
+    CCDSolver, IntegrationParameters, JointSet, RigidBody, RigidBodyBuilder, RigidBodyHandle,
+    RigidBodySet,
+};
+use rapier2d_f64::geometry::{
+    BroadPhase, ColliderBuilder, ColliderHandle, ColliderSet, NarrowPhase, SharedShape,
+};
+use rapier2d_f64::na::Isometry2;
+use rapier2d_f64::pipeline::{PhysicsPipeline, QueryPipeline};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Tunables the world is created with
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WorldConfig {
+    pub integration_parameters: IntegrationParameters,
+    pub player_linear_damping: f64,
+    /// Multiplies the mass of every planet
+    pub gravity_scale: f64,
+    pub controller: ControllerConfig,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        WorldConfig {
+            integration_parameters: IntegrationParameters::default(),
+            player_linear_damping: 0.5,
+            gravity_scale: 1.0,
+            controller: ControllerConfig::default(),
+        }
+    }
+}
+
+/// What the player wants to do during a single physics step, independent of where it came from
+/// (keyboard, tests, ...)
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Input {
+    /// -1.0 is fully left, 1.0 is fully right
+    pub horizontal_movement: f64,
+    /// Should only be true on the step the jump button was pressed
+    pub jump: bool,
+    /// If the jump button is down at all, letting go early makes for a shorter jump
+    pub jump_held: bool,
+}
+
+/// A sensor from the level, see `World::touched_triggers`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Trigger {
+    pub name: String,
+    pub collider: ColliderHandle,
+}
+
+/// The whole simulation, with no knowledge of windows or rendering
+#[derive(Serialize, Deserialize)]
+pub struct World {
+    #[serde(skip)]
+    pipeline: PhysicsPipeline,
+    /// Not saved in snapshots, it's rebuilt from the colliders when one is restored
+    #[serde(skip)]
+    query: QueryPipeline,
+    #[serde(with = "crate::snapshot::maps_as_pairs")]
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    joints: JointSet,
+    ccd_solver: CCDSolver,
+    config: WorldConfig,
+    gravity: Gravity,
+    triggers: Vec<Trigger>,
+
+    player: PlayerController,
+
+    /// Where every body was before the last step, for drawing in between steps. Not worth saving,
+    /// nothing is interpolated for the first frame after loading.
+    #[serde(skip)]
+    previous_positions: HashMap<RigidBodyHandle, Isometry2<f64>>,
+}
+
+impl World {
+    /// A world with the built in level
+    pub fn new(config: WorldConfig) -> Self {
+        Self::from_level(config, &Level::builtin()).expect("the built in level is invalid")
+    }
+
+    pub fn from_level(config: WorldConfig, level: &Level) -> Result<Self, String> {
+        let mut bodies = RigidBodySet::new();
+        let mut colliders = ColliderSet::new();
+
+        let circle = RigidBodyBuilder::new_dynamic()
+            .position(Isometry2::new(V2::new(level.spawn[0], level.spawn[1]), 0.0))
+            .linear_damping(config.player_linear_damping)
+            .user_data(Visual::Player.to_user_data())
+            .build();
+        let circle_collider = ColliderBuilder::new(SharedShape::ball(1.0))
+            .restitution(0.0)
+            .build();
+        let player = bodies.insert(circle);
+        let player_collider = colliders.insert(circle_collider, player, &mut bodies);
+        let player = PlayerController::new(player, player_collider, config.controller.clone());
+
+        let mut gravity = Gravity::new();
+        let mut insert = |(body, collider): (RigidBodyBuilder, ColliderBuilder)| {
+            let body = bodies.insert(body.build());
+            let collider = colliders.insert(collider.build(), body, &mut bodies);
+            (body, collider)
+        };
+        for (i, planet) in level.planets.iter().enumerate() {
+            let (body, _) = insert(
+                planet
+                    .build()
+                    .map_err(|e| format!("planets[{}]: {}", i, e))?,
+            );
+            gravity.add_planet(Planet {
+                body,
+                mass: planet.mass * config.gravity_scale,
+                falloff: planet.falloff,
+            });
+        }
+        for (i, body) in level.bodies.iter().enumerate() {
+            insert(body.build().map_err(|e| format!("bodies[{}]: {}", i, e))?);
+        }
+        let mut triggers = Vec::new();
+        for (i, trigger) in level.triggers.iter().enumerate() {
+            let (_, collider) = insert(
+                trigger
+                    .build()
+                    .map_err(|e| format!("triggers[{}]: {}", i, e))?,
+            );
+            triggers.push(Trigger {
+                name: trigger.name.clone(),
+                collider,
+            });
+        }
+
+        let mut world = World {
+            pipeline: PhysicsPipeline::new(),
+            query: QueryPipeline::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies,
+            colliders,
+            joints: JointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            config,
+            gravity,
+            triggers,
+            player,
+            previous_positions: HashMap::new(),
+        };
+        world.update_query_pipeline();
+        Ok(world)
+    }
+
+    /// Applies the player's input then advances the simulation by one
+    /// `IntegrationParameters::dt`
+    pub fn step(&mut self, input: &Input) {
+        let down = self.player_down();
+        self.player.update(
+            input,
+            self.config.integration_parameters.dt,
+            down,
+            &mut self.bodies,
+            &self.colliders,
+            &self.query,
+        );
+
+        self.previous_positions.clear();
+        for (handle, body) in self.bodies.iter() {
+            self.previous_positions.insert(handle, *body.position());
+        }
+
+        self.gravity.apply(&mut self.bodies);
+
+        // We ignore physics hooks and contact events for now.
+        self.pipeline.step(
+            &V2::zeros(),
+            &self.config.integration_parameters,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.joints,
+            &mut self.ccd_solver,
+            &(),
+            &(),
+        );
+        self.update_query_pipeline();
+    }
+
+    /// Brings the query pipeline up to date with where the colliders are
+    pub(crate) fn update_query_pipeline(&mut self) {
+        self.query.update(&self.bodies, &self.colliders);
+    }
+
+    /// The position of a body `alpha` of the way from where it was before the last step (0.0) to
+    /// where it is now (1.0)
+    pub fn interpolated_position(&self, handle: RigidBodyHandle, alpha: f64) -> Isometry2<f64> {
+        let current = self.bodies[handle].position();
+        match self.previous_positions.get(&handle) {
+            Some(previous) => previous.lerp_slerp(current, alpha),
+            None => *current,
+        }
+    }
+
+    /// A hash of the position and velocity of every body, bit for bit. Two worlds that were
+    /// stepped with the same inputs from the same config will always have the same hash.
+    pub fn state_hash(&self) -> u64 {
+        // FNV-1a, because std's hasher isn't guaranteed to stay the same between rust versions
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut write = |value: f64| {
+            for byte in value.to_bits().to_le_bytes().iter() {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for (_, body) in self.bodies.iter() {
+            let position = body.position();
+            write(position.translation.x);
+            write(position.translation.y);
+            write(position.rotation.re);
+            write(position.rotation.im);
+            write(body.linvel().x);
+            write(body.linvel().y);
+            write(body.angvel());
+        }
+        hash
+    }
+
+    /// The direction gravity pulls on the player
+    pub fn player_down(&self) -> V2 {
+        self.gravity.down_at(
+            &self.bodies,
+            &P2::from(self.player().position().translation.vector),
+        )
+    }
+
+    pub fn gravity(&self) -> &Gravity {
+        &self.gravity
+    }
+
+    pub fn config(&self) -> &WorldConfig {
+        &self.config
+    }
+
+    pub fn bodies(&self) -> &RigidBodySet {
+        &self.bodies
+    }
+
+    pub fn colliders(&self) -> &ColliderSet {
+        &self.colliders
+    }
+
+    pub fn joints(&self) -> &JointSet {
+        &self.joints
+    }
+
+    pub fn query_pipeline(&self) -> &QueryPipeline {
+        &self.query
+    }
+
+    pub fn player_handle(&self) -> RigidBodyHandle {
+        self.player.body()
+    }
+
+    pub fn player(&self) -> &RigidBody {
+        &self.bodies[self.player.body()]
+    }
+
+    pub fn player_controller(&self) -> &PlayerController {
+        &self.player
+    }
+
+    /// How a body should look, `None` for bodies that aren't really there, like triggers
+    pub fn visual(&self, handle: RigidBodyHandle) -> Option<Visual> {
+        Visual::from_user_data(self.bodies.get(handle)?.user_data)
+    }
+
+    pub fn triggers(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    /// The names of the triggers the player is inside of, as of the last step
+    pub fn touched_triggers(&self) -> impl Iterator<Item = &str> {
+        let player = self.player.collider();
+        self.triggers
+            .iter()
+            .filter(move |t| self.narrow_phase.intersection_pair(player, t.collider) == Some(true))
+            .map(|t| t.name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settle(world: &mut World) {
+        for _ in 0..600 {
+            world.step(&Input::default());
+        }
+    }
+
+    #[test]
+    fn player_lands_on_planet() {
+        let mut world = World::new(WorldConfig::default());
+        settle(&mut world);
+        // the planet's top surface is at y = 90 and the player is a ball of radius 1
+        let pos = world.player().position().translation.vector;
+        assert!((pos.y - 89.0).abs() < 0.1, "player at {}", pos);
+        assert!(world.player().linvel().norm() < 0.1);
+    }
+
+    #[test]
+    fn jump_only_when_grounded() {
+        let mut world = World::new(WorldConfig::default());
+        let jump = Input {
+            jump: true,
+            jump_held: true,
+            ..Input::default()
+        };
+
+        // mid air at spawn, jumping does nothing
+        world.step(&jump);
+        assert!(world.player().linvel().y >= 0.0);
+
+        settle(&mut world);
+        world.step(&jump);
+        assert!(world.player().linvel().y < 0.0);
+    }
+
+    #[test]
+    fn many_frames_headless() {
+        let mut world = World::new(WorldConfig::default());
+        for i in 0..5000 {
+            world.step(&Input {
+                jump: i % 120 == 0,
+                jump_held: i % 120 < 60,
+                ..Input::default()
+            });
+        }
+        settle(&mut world);
+
+        // jumping over and over always lands back on the planet
+        let planet = world.gravity().planets()[0].body;
+        let from_center = world.player().position().translation.vector
+            - world.bodies()[planet].position().translation.vector;
+        assert!(
+            (from_center.norm() - 51.0).abs() < 0.1,
+            "player at {}",
+            from_center
+        );
+        assert!(world.player().linvel().norm() < 0.1);
+    }
+
+    #[test]
+    fn movement_follows_planet_surface() {
+        let mut world = World::new(WorldConfig::default());
+        settle(&mut world);
+        assert!((world.player_down() - V2::new(0.0, 1.0)).norm() < 1e-3);
+
+        for _ in 0..30 {
+            world.step(&Input {
+                horizontal_movement: 1.0,
+                ..Input::default()
+            });
+        }
+        // walked clockwise around the planet, so down now points partly back to the left
+        let down = world.player_down();
+        assert!(down.x < -0.05);
+        assert!(down.y > 0.0);
+    }
+
+    #[test]
+    fn interpolation_between_steps() {
+        let mut world = World::new(WorldConfig::default());
+        let player = world.player_handle();
+        // not stepped yet, nothing to interpolate from
+        assert_eq!(
+            world.interpolated_position(player, 0.5),
+            *world.player().position()
+        );
+
+        let before = world.player().position().translation.vector;
+        world.step(&Input::default());
+        let after = world.player().position().translation.vector;
+
+        let at = |alpha| {
+            world
+                .interpolated_position(player, alpha)
+                .translation
+                .vector
+        };
+        assert!((at(0.0) - before).norm() < 1e-9);
+        assert!((at(1.0) - after).norm() < 1e-9);
+        assert!((at(0.5) - (before + after) / 2.0).norm() < 1e-9);
+    }
+
+    #[test]
+    fn level_triggers() {
+        let level = Level::parse(
+            r#"{
+                "spawn": [0, 0],
+                "planets": [
+                    { "position": [0, 60], "radius": 50, "mass": 20, "falloff": "constant" }
+                ],
+                "triggers": [
+                    { "name": "start", "position": [0, 0], "shape": { "type": "ball", "radius": 2 } },
+                    { "name": "far", "position": [50, 0], "shape": { "type": "ball", "radius": 2 } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut world = World::from_level(WorldConfig::default(), &level).unwrap();
+        assert_eq!(world.triggers().len(), 2);
+        world.step(&Input::default());
+        assert_eq!(world.touched_triggers().collect::<Vec<_>>(), vec!["start"]);
+
+        // falls onto the planet and out of the trigger
+        settle(&mut world);
+        assert!(world.player().position().translation.y > 8.0);
+        assert_eq!(world.touched_triggers().count(), 0);
+    }
+}